@@ -1,21 +1,18 @@
-use sha1::digest::typenum::Less;
-
 use crate::builder::Target;
+use crate::cache::{self, CacheConfig};
+use crate::graph::TargetGraph;
+use crate::profiles;
+use crate::toolchain;
 use crate::utils::{self, log, BuildConfig, LogLevel, Package, TargetConfig};
-use std::{fs, default};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::fs;
 use std::io::Write;
 use std::path::Path;
 use std::process::{Command, Stdio};
+use std::sync::Arc;
 
-static BUILD_DIR: &str = ".bld_cpp/bin";
-#[cfg(target_os = "windows")]
-static OBJ_DIR: &str = ".bld_cpp/obj_win32";
-#[cfg(target_os = "linux")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
-#[cfg(target_os = "android")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
-
-///Cleans the local targets
+///Cleans the local targets of the selected profile
 /// # Arguments
 /// * `targets` - A vector of targets to clean
 pub fn clean(targets: &Vec<TargetConfig>) {
@@ -27,24 +24,30 @@ pub fn clean(targets: &Vec<TargetConfig>) {
             );
         });
     }
-    if Path::new(OBJ_DIR).exists() {
-        fs::remove_dir_all(OBJ_DIR).unwrap_or_else(|why| {
+    let obj_dir = profiles::obj_dir();
+    let build_dir = profiles::build_dir();
+    if Path::new(&obj_dir).exists() {
+        fs::remove_dir_all(&obj_dir).unwrap_or_else(|why| {
             log(
                 LogLevel::Error,
                 &format!("Could not remove object directory: {}", why),
             );
         });
-        log(LogLevel::Info, &format!("Cleaning: {}", OBJ_DIR));
+        log(LogLevel::Info, &format!("Cleaning: {}", obj_dir));
+    }
+    let unity_dir = profiles::unity_dir();
+    if Path::new(&unity_dir).exists() {
+        fs::remove_dir_all(&unity_dir).unwrap_or_else(|why| {
+            log(
+                LogLevel::Error,
+                &format!("Could not remove unity directory: {}", why),
+            );
+        });
+        log(LogLevel::Info, &format!("Cleaning: {}", unity_dir));
     }
+    profiles::remove_legacy_outputs();
     for target in targets {
-        //remove hashes
-        #[cfg(target_os = "windows")]
-        let hash_path = format!(".bld_cpp/{}.win32.hash", &target.name);
-        #[cfg(target_os = "linux")]
-        let hash_path = format!(".bld_cpp/{}.linux.hash", &target.name);
-        #[cfg(target_os = "android")]
-        let hash_path = format!(".bld_cpp/{}.linux.hash", &target.name);
-
+        let hash_path = profiles::hash_file_path(&target.name);
         if Path::new(&hash_path).exists() {
             fs::remove_file(&hash_path).unwrap_or_else(|why| {
                 log(
@@ -54,29 +57,8 @@ pub fn clean(targets: &Vec<TargetConfig>) {
             });
             log(LogLevel::Info, &format!("Cleaning: {}", &hash_path));
         }
-        if Path::new(BUILD_DIR).exists() {
-            let mut bin_name = String::new();
-            bin_name.push_str(BUILD_DIR);
-            bin_name.push_str("/");
-            bin_name.push_str(&target.name);
-            #[cfg(target_os = "windows")]
-            if target.typ == "exe" {
-                bin_name.push_str(".exe");
-            } else if target.typ == "dll" {
-                bin_name.push_str(".dll");
-            }
-            #[cfg(target_os = "linux")]
-            if target.typ == "exe" {
-                bin_name.push_str("");
-            } else if target.typ == "dll" {
-                bin_name.push_str(".so");
-            }
-            #[cfg(target_os = "android")]
-            if target.typ == "exe" {
-                bin_name.push_str("");
-            } else if target.typ == "dll" {
-                bin_name.push_str(".so");
-            }
+        if Path::new(&build_dir).exists() {
+            let bin_name = format!("{}/{}", build_dir, target.bin_name());
             if Path::new(&bin_name).exists() {
                 fs::remove_file(&bin_name).unwrap_or_else(|why| {
                     log(
@@ -101,12 +83,10 @@ pub fn clean(targets: &Vec<TargetConfig>) {
 pub fn clean_packages(packages: &Vec<Package>) {
     for pack in packages {
         for target in &pack.target_configs {
-            #[cfg(target_os = "windows")]
-            let pack_bin_path = format!("{}/{}.dll", BUILD_DIR, &target.name);
-            #[cfg(target_os = "linux")]
-            let pack_bin_path = format!("{}/{}.so", BUILD_DIR, &target.name);
-            #[cfg(target_os = "android")]
-            let pack_bin_path = format!("{}/{}.so", BUILD_DIR, &target.name);
+            if target.typ != "dll" && target.typ != "lib" {
+                continue;
+            }
+            let pack_bin_path = format!("{}/{}", profiles::build_dir(), target.bin_name());
 
             if !Path::new(&pack_bin_path).exists() {
                 log(
@@ -115,23 +95,19 @@ pub fn clean_packages(packages: &Vec<Package>) {
                 );
                 continue;
             }
-            let cmd_str = format!("rm {}", &pack_bin_path);
-            log(LogLevel::Debug, cmd_str.as_str());
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(&cmd_str)
-                .output()
-                .expect("failed to execute process");
-            if output.status.success() {
-                log(
+            log(LogLevel::Debug, &format!("Removing: {}", &pack_bin_path));
+            match fs::remove_file(&pack_bin_path) {
+                Ok(()) => log(
                     LogLevel::Log,
                     &format!("Cleaned package: {} of {}", &pack.name, &pack.repo),
-                );
-            } else {
-                log(
+                ),
+                Err(why) => log(
                     LogLevel::Error,
-                    &format!("Could not clean package: {} of {}", &pack.name, &pack.repo),
-                );
+                    &format!(
+                        "Could not clean package: {} of {}: {}",
+                        &pack.name, &pack.repo, why
+                    ),
+                ),
             }
         }
     }
@@ -158,9 +134,10 @@ pub fn build(
             std::process::exit(1);
         });
     }
+    //outputs of builds before profiles are never reused
+    profiles::remove_legacy_outputs();
     if gen_cc {
         let mut cc_file = fs::OpenOptions::new()
-            .write(true)
             .append(true)
             .open("compile_commands.json")
             .unwrap_or_else(|why| {
@@ -178,7 +155,6 @@ pub fn build(
 
     if gen_vsc {
         let mut vsc_file = fs::OpenOptions::new()
-            .write(true)
             .append(true)
             .open(".vscode/c_cpp_properties.json")
             .unwrap_or_else(|why| {
@@ -189,124 +165,48 @@ pub fn build(
                 std::process::exit(1);
             });
 
-        let mut inc_dirs: Vec<String> = targets.iter().map(|t| t.include_dir.clone()).collect();
-        for package in packages {
-            for target in &package.target_configs {
-                inc_dirs.push(target.include_dir.clone());
+        let mut inc_dirs: Vec<String> = Vec::new();
+        let all_targets = targets
+            .iter()
+            .chain(packages.iter().flat_map(|x| &x.target_configs));
+        for target in all_targets {
+            inc_dirs.push(target.include_dir.clone());
+            inc_dirs.extend(target.include_dirs.iter().cloned());
+            for system_dep in &target.system_deps {
+                let package = system_dep.find(&target.origin("system_deps"));
+                inc_dirs.extend(package.include_dirs());
             }
         }
-        let compiler_path: String = build_config.compiler.clone();
-        let mut intellimode: String = String::new();
-        if compiler_path == "gcc" || compiler_path == "g++" {
-            intellimode = "gcc-x64".to_string();
-        } else if compiler_path == "clang" || compiler_path == "clang++" {
-            intellimode = "clang-x64".to_string();
-        } else {
+        let inc_dirs: Vec<String> = inc_dirs.into_iter().unique().collect();
+        //the compiler of c++ sources, which is the one of the toolchain when cross compiling
+        //c++ is a builtin language, so it is always found
+        let cxx = build_config.languages.iter().find(|x| x.is_cxx()).unwrap();
+        let compiler = build_config.compiler_of(cxx);
+        let compiler_path = compiler.split_whitespace().next().unwrap_or_default().to_string();
+        let toolchain = toolchain::toolchain();
+        let intellimode = intellisense_mode(&compiler_path, toolchain).unwrap_or_else(|| {
             log(
                 LogLevel::Error,
                 &format!("Unsupported compiler: {}", compiler_path),
             );
-        }
-
-        #[cfg(target_os = "windows")]
-        let compiler_path = Command::new("sh")
-            .arg("-c")
-            .arg(&format!("where {}", &compiler_path))
-            .output()
-            .expect("failed to execute process")
-            .stdout;
-
-        #[cfg(target_os = "windows")]
-        //Pick the first compiler path
-        let compiler_path = String::from_utf8(compiler_path)
-            .unwrap()
-            .split("\n")
-            .collect::<Vec<&str>>()[0]
-            .to_string()
-            .replace("\r", "")
-            .replace("\\", "/");
-
-        #[cfg(target_os = "windows")]
-        let vsc_json = format!(
-            r#"{{
-    "configurations": [
-        {{
-            "name": "Win32",
-            "includePath": [
-                "{}"
-            ],
-            "defines": [
-                "_DEBUG",
-                "UNICODE",
-                "_UNICODE"
-            ],
-            "compilerPath": "{}",
-            "cStandard": "c11",
-            "cppStandard": "c++17",
-            "intelliSenseMode": "windows-{}"
-        }}
-    ],
-    "version": 4
-}}"#,
-            inc_dirs.join("\",\n\t\t\t\t\""),
-            compiler_path,
-            intellimode
-        );
-
-        #[cfg(target_os = "linux")]
-        let compiler_path = Command::new("sh")
-            .arg("-c")
-            .arg(&format!("which {}", &compiler_path))
-            .output()
-            .expect("failed to execute process")
-            .stdout;
+            String::new()
+        });
 
-        #[cfg(target_os = "linux")]
-        let compiler_path = String::from_utf8(compiler_path).unwrap().replace('\n', "");
+        //the full path of the compiler, found the way a shell would
+        let compiler_path = utils::find_program(&compiler_path)
+            .map(|x| x.to_string_lossy().replace('\\', "/"))
+            .unwrap_or(compiler_path);
 
-        #[cfg(target_os = "linux")]
-        let vsc_json = format!(
-            r#"{{
-    "configurations": [
-        {{
-            "name": "Linux",
-            "includePath": [
-                "{}"
-            ],
-            "defines": [
-                "_DEBUG",
-                "UNICODE",
-                "_UNICODE"
-            ],
-            "compilerPath": "{}",
-            "cStandard": "c11",
-            "cppStandard": "c++17",
-            "intelliSenseMode": "linux-{}"
-        }}
-    ],
-    "version": 4
-}}"#,
-            inc_dirs.join("\",\n\t\t\t\t\""),
-            compiler_path,
-            intellimode
-        );
-        #[cfg(target_os = "android")]
-        let compiler_path = Command::new("sh")
-            .arg("-c")
-            .arg(&format!("which {}", &compiler_path))
-            .output()
-            .expect("failed to execute process")
-            .stdout;
-
-        #[cfg(target_os = "android")]
-        let compiler_path = String::from_utf8(compiler_path).unwrap().replace('\n', "");
-
-        #[cfg(target_os = "android")]
+        let configuration_name = match toolchain.os.as_str() {
+            "windows" => "Win32",
+            "macos" => "Mac",
+            _ => "Linux",
+        };
         let vsc_json = format!(
             r#"{{
     "configurations": [
         {{
-            "name": "Linux",
+            "name": "{}",
             "includePath": [
                 "{}"
             ],
@@ -318,11 +218,12 @@ pub fn build(
             "compilerPath": "{}",
             "cStandard": "c11",
             "cppStandard": "c++17",
-            "intelliSenseMode": "linux-{}"
+            "intelliSenseMode": "{}"
         }}
     ],
     "version": 4
 }}"#,
+            configuration_name,
             inc_dirs.join("\",\n\t\t\t\t\""),
             compiler_path,
             intellimode
@@ -340,13 +241,26 @@ pub fn build(
             });
     }
 
+    let mut created = HashMap::new();
+    for package in packages {
+        for target in &package.target_configs {
+            Target::new(
+                &package.build_config,
+                target,
+                &package.target_configs,
+                packages,
+                &mut created,
+            );
+        }
+    }
     for target in targets {
-        let mut tgt = Target::new(build_config, &target, &targets, &packages);
-        tgt.build(gen_cc);
+        Target::new(build_config, target, targets, packages, &mut created);
     }
+    let created: Vec<Arc<Target>> = created.into_values().collect();
+    Target::build_all(&created, gen_cc);
+    cache::finish_build();
     if gen_cc {
         let mut cc_file = fs::OpenOptions::new()
-            .write(true)
             .read(true)
             .append(true)
             .open("compile_commands.json")
@@ -378,7 +292,7 @@ pub fn run(
     targets: &Vec<TargetConfig>,
     packages: &Vec<Package>,
 ) {
-    let trgt = Target::new(build_config, exe_target, &targets, &packages);
+    let trgt = Target::new(build_config, exe_target, targets, packages, &mut HashMap::new());
     if !Path::new(&trgt.bin_path).exists() {
         log(
             LogLevel::Error,
@@ -387,9 +301,9 @@ pub fn run(
         std::process::exit(1);
     }
     log(LogLevel::Log, &format!("Running: {}", &trgt.bin_path));
-    let mut cmd = std::process::Command::new(&trgt.bin_path);
-    if bin_args.is_some() {
-        for arg in bin_args.unwrap() {
+    let mut cmd = Command::new(&trgt.bin_path);
+    if let Some(bin_args) = bin_args {
+        for arg in bin_args {
             cmd.arg(arg);
         }
     }
@@ -397,7 +311,7 @@ pub fn run(
         .stdout(Stdio::inherit())
         .stderr(Stdio::inherit());
     let output = cmd.output();
-    if !output.is_err() {
+    if output.is_ok() {
         log(LogLevel::Info, &format!("  Success: {}", &trgt.bin_path));
     } else {
         log(LogLevel::Error, &format!("  Error: {}", &trgt.bin_path));
@@ -413,7 +327,7 @@ pub fn init(project_name: &str, is_c: bool) {
         std::process::exit(1);
     }
     //initialise git repo in project directory
-    let mut cmd = std::process::Command::new("git");
+    let mut cmd = Command::new("git");
     cmd.arg("init").arg(project_name);
     let output = cmd.output();
     if output.is_err() {
@@ -422,12 +336,7 @@ pub fn init(project_name: &str, is_c: bool) {
         std::process::exit(1);
     }
 
-    #[cfg(target_os = "windows")]
-    let config_file = project_name.to_owned() + "/config_win32.toml";
-    #[cfg(target_os = "linux")]
-    let config_file = project_name.to_owned() + "/config_linux.toml";
-    #[cfg(target_os = "android")]
-    let config_file = project_name.to_owned() + "/config_linux.toml";
+    let config_file = project_name.to_owned() + "/builder_cpp.toml";
 
     if Path::new(&config_file).exists() {
         log(LogLevel::Error, &format!("{} already exists", config_file));
@@ -438,6 +347,7 @@ pub fn init(project_name: &str, is_c: bool) {
     let mut config_file = fs::OpenOptions::new()
         .write(true)
         .create(true)
+        .truncate(true)
         .open(config_file)
         .unwrap_or_else(|why| {
             log(
@@ -496,6 +406,7 @@ pub fn init(project_name: &str, is_c: bool) {
         let mut main_file = fs::OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&main_path)
             .unwrap_or_else(|why| {
                 log(
@@ -522,6 +433,7 @@ pub fn init(project_name: &str, is_c: bool) {
         let mut gitignore_file = fs::OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(&gitignore_path)
             .unwrap_or_else(|why| {
                 log(
@@ -558,16 +470,12 @@ pub fn parse_config() -> (
     Vec<utils::TargetConfig>,
     Vec<utils::Package>,
 ) {
-    #[cfg(target_os = "linux")]
-    let (build_config, targets) = utils::parse_config("./config_linux.toml", true);
-    #[cfg(target_os = "windows")]
-    let (build_config, targets) = utils::parse_config("./config_win32.toml", true);
-    #[cfg(target_os = "android")]
-    let (build_config, targets) = utils::parse_config("./config_linux.toml", true);
+    let config_path = toolchain::config_path(".");
+    let (build_config, targets) = utils::parse_config(&config_path, true);
 
     let mut num_exe = 0;
     let mut exe_target: Option<&utils::TargetConfig> = None;
-    if targets.len() == 0 {
+    if targets.is_empty() {
         utils::log(utils::LogLevel::Error, "No targets in config");
         std::process::exit(1);
     } else {
@@ -619,16 +527,92 @@ pub fn parse_config() -> (
     } 
     */
 
-    #[cfg(target_os = "linux")]
-    let packages = utils::Package::parse_packages("./config_linux.toml");
-    #[cfg(target_os = "android")]
-    let packages = utils::Package::parse_packages("./config_linux.toml");
-    #[cfg(target_os = "windows")]
-    let packages = utils::Package::parse_packages("./config_win32.toml");
+    let packages = utils::Package::parse_packages(&config_path);
+    check_target_names(&targets, &packages, &config_path);
+
+    let package_target_names: Vec<String> = packages
+        .iter()
+        .flat_map(|x| x.target_configs.iter().map(|y| y.name.clone()))
+        .collect();
+    let targets = TargetGraph::new(&targets, &package_target_names).arrange(&targets);
 
     (build_config, targets, packages)
 }
 
+//returns the intelliSense mode of vscode for a compiler building for a toolchain,
+//like linux-gcc-x64 or windows-clang-arm64, or None if the compiler is not gcc or clang
+fn intellisense_mode(compiler: &str, toolchain: &toolchain::Toolchain) -> Option<String> {
+    let name = Path::new(compiler)
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    //cross compilers are prefixed with their triple, like aarch64-linux-gnu-g++
+    let family = if name.contains("clang") {
+        "clang"
+    } else if name.ends_with("gcc") || name.ends_with("g++") || name == "cc" || name == "c++" {
+        "gcc"
+    } else {
+        return None;
+    };
+    let platform = match toolchain.os.as_str() {
+        "windows" => "windows",
+        "macos" => "macos",
+        _ => "linux",
+    };
+    let arch = match toolchain.triple.as_deref() {
+        Some(triple) => triple.split('-').next().unwrap_or_default(),
+        None => std::env::consts::ARCH,
+    };
+    let arch = match arch {
+        "aarch64" | "arm64" => "arm64",
+        "x86" | "i386" | "i486" | "i586" | "i686" => "x86",
+        _ if arch.starts_with("arm") => "arm",
+        _ => "x64",
+    };
+    Some(format!("{}-{}-{}", platform, family, arch))
+}
+
+//exits if a target has the name of a target of a package, or two packages have targets of one name
+//targets are built once per name and their binaries and objects are placed by name, so they would clash
+fn check_target_names(targets: &[TargetConfig], packages: &[Package], config_path: &str) {
+    let mut owners: HashMap<&str, &str> = HashMap::new();
+    for package in packages {
+        for target in &package.target_configs {
+            //a package used by several packages is listed once for each of them
+            match owners.insert(&target.name, &package.name) {
+                Some(owner) if owner != package.name => {
+                    log(
+                        LogLevel::Error,
+                        &format!(
+                            "Packages {} and {} both have a target named {}",
+                            owner, package.name, target.name
+                        ),
+                    );
+                    std::process::exit(1);
+                }
+                _ => {}
+            }
+        }
+    }
+    for target in targets {
+        if let Some(owner) = owners.get(target.name.as_str()) {
+            log(
+                LogLevel::Error,
+                &format!(
+                    "Target {} has the name of a target of package {}",
+                    target.name, owner
+                ),
+            );
+            log(LogLevel::Error, &format!("  In {}", config_path));
+            log(
+                LogLevel::Error,
+                "Binaries and objects are placed by the names of targets, so rename the target",
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
 pub fn pre_gen_cc() {
     if !Path::new("./compile_commands.json").exists() {
         fs::File::create(Path::new("./compile_commands.json")).unwrap();
@@ -653,7 +637,7 @@ pub fn pre_gen_vsc() {
 
 pub fn clean_packages_wrapper(packages: &Vec<utils::Package>) {
     utils::log(utils::LogLevel::Log, "Cleaning packages...");
-    clean_packages(&packages);
+    clean_packages(packages);
 }
 
 pub fn update_packages(packages: &Vec<utils::Package>) {
@@ -669,3 +653,60 @@ pub fn restore_packages(packages: &Vec<utils::Package>) {
         package.restore();
     }
 }
+
+/// Shows where the object cache is, how large it is and how often it was hit
+pub fn cache_stats() {
+    cache::print_stats(&cache_config());
+}
+
+/// Removes every object from the object cache
+pub fn clear_cache() {
+    utils::log(utils::LogLevel::Log, "Clearing the cache...");
+    cache::clear(&cache_config());
+}
+
+//returns the cache settings of the config in the current directory,
+//so that the cache commands also work outside of projects
+fn cache_config() -> CacheConfig {
+    let path = toolchain::config_path(".");
+    if !Path::new(&path).exists() {
+        return CacheConfig::default();
+    }
+    let (build_config, _) = utils::parse_config(&path, false);
+    build_config.cache.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toolchain(os: &str, triple: Option<&str>) -> toolchain::Toolchain {
+        let mut toolchain = toolchain::Toolchain::host();
+        toolchain.os = os.to_string();
+        toolchain.triple = triple.map(String::from);
+        toolchain
+    }
+
+    #[test]
+    fn intellisense_modes_follow_the_toolchain() {
+        let mingw = toolchain("windows", Some("x86_64-w64-mingw32"));
+        let mode = intellisense_mode("/usr/bin/x86_64-w64-mingw32-g++", &mingw);
+        assert_eq!(mode.as_deref(), Some("windows-gcc-x64"));
+
+        let aarch64 = toolchain("linux", Some("aarch64-linux-gnu"));
+        let mode = intellisense_mode("aarch64-linux-gnu-gcc", &aarch64);
+        assert_eq!(mode.as_deref(), Some("linux-gcc-arm64"));
+
+        let android = toolchain("android", Some("armv7a-linux-androideabi"));
+        let mode = intellisense_mode("armv7a-linux-androideabi21-clang++", &android);
+        assert_eq!(mode.as_deref(), Some("linux-clang-arm"));
+
+        let macos = toolchain("macos", Some("i686-apple-darwin"));
+        assert_eq!(
+            intellisense_mode("clang++", &macos).as_deref(),
+            Some("macos-clang-x86")
+        );
+
+        assert_eq!(intellisense_mode("icpx", &aarch64), None);
+    }
+}
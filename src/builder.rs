@@ -1,113 +1,199 @@
 //! This module contains the buiild related functions
 
-use crate::hasher;
+use crate::cache;
+use crate::flags;
+use crate::hasher::{self, HashStore};
+use crate::jobs;
+use crate::languages::Language;
+use crate::profiles;
+use crate::toolchain;
 use crate::utils::{self, log, BuildConfig, LogLevel, Package, TargetConfig};
 use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
 use itertools::Itertools;
-use rayon::{prelude::*, string};
+use rayon::prelude::*;
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Read, Write};
-use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::sync::{Arc, Mutex};
-
-static BUILD_DIR: &str = ".bld_cpp/bin";
-#[cfg(target_os = "windows")]
-static OBJ_DIR: &str = ".bld_cpp/obj_win32";
-#[cfg(target_os = "linux")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
-#[cfg(target_os = "android")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
+use std::io::Write;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex, OnceLock};
+
+static CC_LOCK: Mutex<()> = Mutex::new(());
+//the launcher every compile command is run with, split into arguments
+static LAUNCHER: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Programs that are commonly put in front of a compiler to cache or distribute compiling
+/// They are left out of compile_commands.json, even when they are part of the compiler
+pub const KNOWN_LAUNCHERS: [&str; 4] = ["ccache", "sccache", "distcc", "icecc"];
+
+/// Sets the launcher every compile command is run with, like ccache or sccache
+/// The launcher is not part of the recorded commands, so adding or removing it rebuilds nothing
+/// # Arguments
+/// * `launcher` - The launcher, which may be followed by arguments
+/// * `origin` - Where the launcher is set, shown if it cannot be used
+pub fn set_launcher(launcher: &str, origin: &str) {
+    let launcher = utils::split_flags(launcher, origin);
+    let Some(program) = launcher.first() else {
+        return;
+    };
+    if utils::find_program(program).is_none() {
+        log(
+            LogLevel::Error,
+            &format!("Could not find launcher: {}", program),
+        );
+        log(LogLevel::Error, &format!("  In {}", origin));
+        std::process::exit(1);
+    }
+    log(
+        LogLevel::Info,
+        &format!("Using launcher: {}", utils::join_args(&launcher)),
+    );
+    if LAUNCHER.set(launcher).is_err() {
+        log(LogLevel::Warn, "Launcher already set");
+    }
+}
 
 //Represents a target
 pub struct Target<'a> {
     srcs: Vec<Src>,
     build_config: &'a BuildConfig,
     target_config: &'a TargetConfig,
-    dependant_includes: HashMap<String, Vec<String>>,
     pub bin_path: String,
     hash_file_path: String,
-    path_hash: HashMap<String, String>,
-    dependant_libs: Vec<Target<'a>>,
+    path_hash: Mutex<HashStore>,
+    dependant_libs: Vec<Arc<Target<'a>>>,
+    include_dirs: Vec<String>,
+    defines: Vec<String>,
+    interface_include_dirs: Vec<String>,
+    interface_defines: Vec<String>,
+    //the compile flags of the system deps, passed on like include dirs and defines
+    system_cflags: Vec<String>,
+    interface_system_cflags: Vec<String>,
+    //flags and libs split into arguments, with their subcommands expanded
+    cflags: Vec<String>,
+    cxxflags: Vec<String>,
+    libs: Vec<String>,
+    //the compiler and flags of every language of the sources, by the name of the language
+    compilers: HashMap<String, Vec<String>>,
+    language_flags: HashMap<String, Vec<String>>,
+    //the linker and the flags passed to it, empty for targets that are not linked
+    linker: Vec<String>,
+    link_flags: Vec<String>,
+    //the optimization of the target, or else of the profile
+    optimization: Option<String>,
+    //the precompiled header, its object is the precompiled file and it is built for itself
+    pch: Option<Src>,
+    //the generated sources including batches of sources, with the indices of those sources
+    unity_batches: Vec<(Src, Vec<usize>)>,
+    //whether the object of each batch is linked instead of the objects of its sources
+    linked_batches: Mutex<Vec<bool>>,
     packages: &'a Vec<Package>,
 }
 
+//Tracks which targets are ready to be linked while building in parallel
+struct Schedule<'a, 'b> {
+    targets: &'b [Arc<Target<'a>>],
+    dependents: Vec<Vec<usize>>,
+    state: Mutex<ScheduleState>,
+}
+
+struct ScheduleState {
+    //number of deps of each target that are not linked yet
+    deps_left: Vec<usize>,
+    //None while the sources of a target are being compiled
+    srcs_compiled: Vec<Option<bool>>,
+}
+
+impl<'a, 'b> Schedule<'a, 'b> {
+    //links a target and starts linking the dependents that are ready afterwards
+    fn link<'s>(&'s self, index: usize, scope: &rayon::Scope<'s>) {
+        let srcs_compiled = self.state.lock().unwrap().srcs_compiled[index].unwrap();
+        self.targets[index].link_if_needed(srcs_compiled);
+
+        let mut state = self.state.lock().unwrap();
+        for &dependent in &self.dependents[index] {
+            state.deps_left[dependent] -= 1;
+            if state.deps_left[dependent] == 0 && state.srcs_compiled[dependent].is_some() {
+                scope.spawn(move |scope| self.link(dependent, scope));
+            }
+        }
+    }
+}
+
 //Represents a source file
 //A single C or Cpp file
 struct Src {
     path: String,
     name: String,
     obj_name: String,
+    dep_path: String,
     bin_path: String,
-    dependant_includes: Vec<String>,
+    language: Language,
 }
 
 impl<'a> Target<'a> {
-    /// Creates a new target
+    /// Creates a new target along with the targets it depends on
+    /// Targets that were already created are reused, so every target is only created once
     /// # Arguments
     /// * `build_config` - Build config
     /// * `target_config` - Target config
     /// * `targets` - All targets
     /// * `packages` - All packages
+    /// * `created` - The targets created so far by name, the new target is added to it
     pub fn new(
         build_config: &'a BuildConfig,
         target_config: &'a TargetConfig,
         targets: &'a Vec<TargetConfig>,
         packages: &'a Vec<Package>,
-    ) -> Self {
+        created: &mut HashMap<String, Arc<Target<'a>>>,
+    ) -> Arc<Self> {
+        if let Some(target) = created.get(&target_config.name) {
+            return Arc::clone(target);
+        }
         let srcs = Vec::new();
-        let dependant_includes: HashMap<String, Vec<String>> = HashMap::new();
-
-        let mut bin_path = String::new();
-        bin_path.push_str(BUILD_DIR);
-        bin_path.push_str("/");
-        bin_path.push_str(&target_config.name);
-        #[cfg(target_os = "windows")]
-        if target_config.typ == "exe" {
-            bin_path.push_str(".exe");
-        } else if target_config.typ == "dll" {
-            bin_path.push_str(".dll");
-        }
-        #[cfg(target_os = "linux")]
-        if target_config.typ == "exe" {
-            bin_path.push_str("");
-        } else if target_config.typ == "dll" {
-            bin_path.push_str(".so");
-        }
-        #[cfg(target_os = "android")]
-        if target_config.typ == "exe" {
-            bin_path.push_str("");
-        } else if target_config.typ == "dll" {
-            bin_path.push_str(".so");
-        }
-
-        #[cfg(target_os = "windows")]
-        let hash_file_path = format!(".bld_cpp/{}.win32.hash", &target_config.name);
-        #[cfg(target_os = "linux")]
-        let hash_file_path = format!(".bld_cpp/{}.linux.hash", &target_config.name);
-        #[cfg(target_os = "android")]
-        let hash_file_path = format!(".bld_cpp/{}.linux.hash", &target_config.name);
-
-        let path_hash = hasher::load_hashes_from_file(&hash_file_path);
+
+        let bin_path = format!("{}/{}", profiles::build_dir(), target_config.bin_name());
+
+        let hash_file_path = profiles::hash_file_path(&target_config.name);
+
+        let path_hash = Mutex::new(hasher::load_hashes_from_file(&hash_file_path));
         let mut dependant_libs = Vec::new();
         for dependant_lib in &target_config.deps {
-            for target in targets {
-                if target.name == *dependant_lib {
-                    utils::log(LogLevel::Info, 
-                        &format!("the target name is {0} and deplin is {1}", 
-                        target.name, dependant_lib));
-                    dependant_libs.push(Target::new(build_config, target, targets, packages));
+            if let Some(target) = targets.iter().find(|x| x.name == dependant_lib.name) {
+                dependant_libs.push(Target::new(build_config, target, targets, packages, created));
+                continue;
+            }
+            //targets of packages can be depended upon just like local ones
+            let pkg_target = packages.iter().find_map(|pkg| {
+                pkg.target_configs
+                    .iter()
+                    .find(|x| x.name == dependant_lib.name)
+                    .map(|target| (pkg, target))
+            });
+            match pkg_target {
+                Some((pkg, target)) => dependant_libs.push(Target::new(
+                    &pkg.build_config,
+                    target,
+                    &pkg.target_configs,
+                    packages,
+                    created,
+                )),
+                None => {
+                    utils::log(
+                        LogLevel::Error,
+                        &format!("Dependant lib not found: {}", dependant_lib.name),
+                    );
+                    std::process::exit(1);
                 }
             }
         }
         for dep_lib in &dependant_libs {
             if dep_lib.target_config.typ == "exe"  {
-                utils::log(LogLevel::Error, "Can add only dlls or hdrs as dependant libs");
+                utils::log(LogLevel::Error, "Can add only dlls, libs or hdrs as dependant libs");
                 utils::log(
                     LogLevel::Error,
-                    &format!("Target: {} is not a dll or hdr", dep_lib.target_config.name),
+                    &format!("Target: {} is not a dll, lib or hdr", dep_lib.target_config.name),
                 );
                 utils::log(
                     LogLevel::Error,
@@ -135,105 +221,232 @@ impl<'a> Target<'a> {
                 std::process::exit(1);
             }
         }
-        if target_config.deps.len() > dependant_libs.len() + packages.len() {
-            for deplib in dependant_libs {
-                utils::log(LogLevel::Error, &format!("dep libs {:?}", deplib.bin_path));  
+        //a target always uses and passes on its own include dirs and defines,
+        //what it gets from its deps depends on their visibility
+        let mut include_dirs = vec![target_config.include_dir.clone()];
+        include_dirs.extend(target_config.include_dirs.iter().cloned());
+        let mut defines = target_config.defines.clone();
+        //system deps are looked up before anything is built, so a missing one fails the build early
+        let mut system_cflags = Vec::new();
+        let mut system_libs = Vec::new();
+        for system_dep in &target_config.system_deps {
+            let package = system_dep.find(&target_config.origin("system_deps"));
+            system_cflags.extend(package.cflags);
+            system_libs.extend(package.libs);
+        }
+        let mut interface_include_dirs = include_dirs.clone();
+        let mut interface_defines = defines.clone();
+        let mut interface_system_cflags = system_cflags.clone();
+        for (dep, dep_lib) in target_config.deps.iter().zip(&dependant_libs) {
+            if dep.visibility != "interface" {
+                include_dirs.extend(dep_lib.interface_include_dirs.iter().cloned());
+                defines.extend(dep_lib.interface_defines.iter().cloned());
+                system_cflags.extend(dep_lib.interface_system_cflags.iter().cloned());
             }
-            utils::log(LogLevel::Error, "Dependant libs not found");
-            utils::log(
-                LogLevel::Error,
-                &format!("Dependant libs: {:?}", target_config.deps),
-            );
-            utils::log(
-                LogLevel::Error,
-                &format!(
-                    "Found libs: {:?}",
-                    targets
-                        .iter()
-                        .map(|x| {
-                            if x.typ == "dll" || x.typ == "hdr" {
-                                x.name.clone()
-                            } else {
-                                "".to_string()
-                            }
-                        })
-                        .collect::<Vec<String>>()
-                        .into_iter()
-                        .filter(|x| x != "")
-                        .collect::<Vec<String>>()
-                ),
-            );
-            std::process::exit(1);
+            if dep.visibility != "private" {
+                interface_include_dirs.extend(dep_lib.interface_include_dirs.iter().cloned());
+                interface_defines.extend(dep_lib.interface_defines.iter().cloned());
+                interface_system_cflags.extend(dep_lib.interface_system_cflags.iter().cloned());
+            }
+        }
+        let include_dirs = include_dirs.into_iter().unique().collect();
+        let defines = defines.into_iter().unique().collect();
+        let interface_include_dirs = interface_include_dirs.into_iter().unique().collect();
+        let interface_defines = interface_defines.into_iter().unique().collect();
+        //the flags of the profile come last, so they override those of the target
+        //every flag is split once here, so subcommands are expanded once per target
+        let profile = build_config.profile();
+        let profile_key = |key: &str| format!("profile.{}.{}", profile.name, key);
+        let split_flags =
+            |flags: &str, key: &str| utils::split_flags(flags, &target_config.origin(key));
+        let mut cflags = split_flags(&target_config.cflags, "cflags");
+        cflags.extend(split_flags(&profile.cflags, &profile_key("cflags")));
+        let mut cxxflags = split_flags(&target_config.cxxflags, "cxxflags");
+        cxxflags.extend(split_flags(&profile.cxxflags, &profile_key("cxxflags")));
+        //the libs of system deps are passed on by static and header only libs like their own libs
+        let mut libs = split_flags(&target_config.libs, "libs");
+        libs.extend(system_libs);
+        let mut link_flags = Vec::new();
+        if target_config.typ == "exe" || target_config.typ == "dll" {
+            //without ldflags the cflags are passed to the linker, as they were before ldflags existed
+            link_flags = match &target_config.ldflags {
+                Some(ldflags) => split_flags(ldflags, "ldflags"),
+                None => cflags.clone(),
+            };
+            link_flags.extend(split_flags(&profile.ldflags, &profile_key("ldflags")));
         }
+        //the flags of a cross compiling toolchain come first, so targets can override them
+        let toolchain = toolchain::toolchain();
+        if target_config.typ == "exe" || target_config.typ == "dll" {
+            link_flags.splice(0..0, toolchain.link_flags());
+        }
+        cflags.splice(0..0, toolchain.compile_flags());
+        let optimization = target_config
+            .optimization
+            .clone()
+            .or_else(|| profile.optimization.clone());
 
         let mut target = Target::<'a> {
             srcs,
             build_config,
             target_config,
-            dependant_includes,
             bin_path,
             path_hash,
             hash_file_path,
             dependant_libs,
+            include_dirs,
+            defines,
+            interface_include_dirs,
+            interface_defines,
+            system_cflags,
+            interface_system_cflags,
+            cflags,
+            cxxflags,
+            libs,
+            compilers: HashMap::new(),
+            language_flags: HashMap::new(),
+            linker: Vec::new(),
+            link_flags,
+            optimization,
+            pch: None,
+            unity_batches: Vec::new(),
+            linked_batches: Mutex::new(Vec::new()),
             packages,
         };
-        target_config.src
-            .as_ref()
-            .map(
-                |src| target.
-                get_srcs(&src, target_config)
-            ).unwrap_or(vec![]);
+        //header only targets have nothing to compile
+        if target_config.typ != "hdr" {
+            for path in target_config.get_src_paths(&build_config.languages) {
+                target.add_src(path);
+            }
+        }
+        for language in target
+            .srcs
+            .iter()
+            .map(|x| &x.language)
+            .unique_by(|x| &x.name)
+        {
+            let compiler = build_config.compiler_of(language);
+            let compiler_key = build_config.compiler_key(language);
+            let flags_key = format!("languages.{}.flags", language.name);
+            target
+                .compilers
+                .insert(language.name.clone(), split_flags(compiler, &compiler_key));
+            target.language_flags.insert(
+                language.name.clone(),
+                split_flags(&language.flags, &flags_key),
+            );
+        }
+        if let Some(pch) = &target_config.pch {
+            target.pch = Some(target.new_pch(pch));
+        }
+        if target_config.unity {
+            target.unity_batches = target.new_unity_batches();
+        }
+        if target_config.typ == "exe" || target_config.typ == "dll" {
+            let has_cxx = target.has_cxx_objects();
+            let linker = build_config.linker(has_cxx);
+            target.linker = split_flags(linker, &build_config.linker_key(has_cxx));
+        }
+        let target = Arc::new(target);
+        created.insert(target_config.name.clone(), Arc::clone(&target));
         target
     }
 
-    /// Builds the target
+    /// Builds the target, compiling its sources and linking it if needed
+    /// The targets it depends on have to be built already
     /// # Arguments
     /// * `gen_cc` - Generate compile_commands.json
-    pub fn build(&mut self, gen_cc: bool) {
-        if !Path::new(".bld_cpp").exists() {
-            std::fs::create_dir(".bld_cpp").unwrap_or_else(|why| {
-                utils::log(
-                    LogLevel::Error,
-                    &format!("Couldn't create .bld_cpp directory: {}", why),
-                );
-                std::process::exit(1);
-            });
+    pub fn build(&self, gen_cc: bool) {
+        let srcs_compiled = self.compile(gen_cc);
+        self.link_if_needed(srcs_compiled);
+    }
+
+    /// Compiles the sources of the target that changed
+    /// Returns true if any source file was compiled
+    /// # Arguments
+    /// * `gen_cc` - Generate compile_commands.json
+    pub fn compile(&self, gen_cc: bool) -> bool {
+        Target::create_hash_dir(&self.hash_file_path);
+        let mut path_hash = self.path_hash.lock().unwrap();
+        let total_srcs = self.srcs.len();
+        //the pch has to be up to date before the sources including it are checked
+        let pch_built = match &self.pch {
+            Some(pch) => self.build_pch(pch, &mut path_hash),
+            None => false,
+        };
+        for (batch, members) in &self.unity_batches {
+            self.write_unity_batch(batch, members);
         }
-        for pkg in self.packages {
-            for target in &pkg.target_configs {
-                let empty: Vec<Package> = Vec::new();
-                if target.typ == "dll" {
-                    let mut pkg_tgt =
-                        Target::new(&pkg.build_config, &target, &pkg.target_configs, &empty);
-                    pkg_tgt.build(gen_cc);
-                } else if target.typ == "hdr" {
-                    // add headers from header
-                    log(LogLevel::Info, "There is nothing to build")
-                    // let mut pkg_tgt = 
-                    //    Target::new(&pkg.build_config, &target, &pkg.target_configs, &empty);
-                }
+        //every source is checked once, in parallel since checking may hash files
+        let current_hashes = &*path_hash;
+        let mut srcs_to_build: Vec<(&Src, Vec<String>)> = self
+            .srcs
+            .par_iter()
+            .filter_map(|src| {
+                let cmd = self.compile_cmd(src);
+                let to_build = self.needs_build(src, &cmd, current_hashes);
+                log(LogLevel::Debug, &format!("{}: {}", src.path, to_build));
+                to_build.then_some((src, cmd))
+            })
+            .collect();
+        //unity batches are only compiled when the whole target is,
+        //otherwise the sources of the batches that changed are compiled one by one
+        let batch_cmds: Vec<(Vec<String>, bool)> = self
+            .unity_batches
+            .par_iter()
+            .map(|(batch, _)| {
+                let cmd = self.compile_cmd(batch);
+                let to_build = self.needs_build(batch, &cmd, current_hashes);
+                (cmd, to_build)
+            })
+            .collect();
+        let full_build =
+            srcs_to_build.len() == total_srcs && batch_cmds.iter().all(|(_, to_build)| *to_build);
+        let mut linked_batches = Vec::new();
+        for ((batch, members), (cmd, to_build)) in self.unity_batches.iter().zip(batch_cmds) {
+            let linked = full_build || !to_build;
+            if linked {
+                let member_objs: Vec<&str> = members
+                    .iter()
+                    .map(|x| self.srcs[*x].obj_name.as_str())
+                    .collect();
+                srcs_to_build.retain(|(src, _)| !member_objs.contains(&src.obj_name.as_str()));
             }
-        }
-        let mut to_link: bool = false;
-        let mut link_causer: Vec<&str> = Vec::new();
-        let mut srcs_needed = 0;
-        let total_srcs = self.srcs.len();
-        let mut src_ccs = Vec::new();
-        for src in &self.srcs {
-            let (to_build, _) = src.to_build(&self.path_hash);
-            log(LogLevel::Debug, &format!("{}: {}", src.path, to_build));
-            if to_build {
-                to_link = true;
-                link_causer.push(&src.path);
-                srcs_needed += 1;
+            if full_build {
+                srcs_to_build.push((batch, cmd));
             }
-            if gen_cc {
-                src_ccs.push(self.gen_cc(&src));
+            linked_batches.push(linked);
+        }
+        *self.linked_batches.lock().unwrap() = linked_batches;
+        let srcs_needed = srcs_to_build.len();
+
+        //forget the objects of sources that were removed
+        let num_outputs = path_hash.len();
+        path_hash.retain(|output, _| {
+            *output == self.bin_path
+                || self.srcs.iter().any(|x| x.obj_name == *output)
+                || self.pch.as_ref().is_some_and(|x| x.obj_name == *output)
+                || self
+                    .unity_batches
+                    .iter()
+                    .any(|(x, _)| x.obj_name == *output)
+        });
+        //unchanged files with new metadata are recorded, so they are not hashed again
+        let mut refreshed = path_hash.len() != num_outputs || pch_built;
+        let batches = self.unity_batches.iter().map(|(x, _)| x);
+        for src in self.srcs.iter().chain(&self.pch).chain(batches) {
+            if !srcs_to_build.iter().any(|(x, _)| x.obj_name == src.obj_name) {
+                refreshed |= hasher::refresh_hashes(&src.obj_name, &mut path_hash);
             }
         }
         if gen_cc {
+            let mut src_ccs = Vec::new();
+            for src in &self.srcs {
+                src_ccs.push(self.gen_cc(src));
+            }
+            //targets are compiled in parallel, so writes to the file must not interleave
+            let _cc_lock = CC_LOCK.lock().unwrap();
             let mut file = std::fs::OpenOptions::new()
-                .write(true)
                 .append(true)
                 .open("./compile_commands.json")
                 .unwrap();
@@ -243,11 +456,28 @@ impl<'a> Target<'a> {
                 }
             }
         }
-        if to_link {
+        if srcs_needed == 0 {
+            if refreshed {
+                hasher::save_hashes_to_file(&self.hash_file_path, &path_hash);
+            }
+            return false;
+        }
+        log(
+            LogLevel::Log,
+            &format!("Compiling Target: {}", &self.target_config.name),
+        );
+        if full_build && !self.unity_batches.is_empty() {
+            let batched: usize = self.unity_batches.iter().map(|(_, x)| x.len()).sum();
             log(
                 LogLevel::Log,
-                &format!("Compiling Target: {}", &self.target_config.name),
+                &format!(
+                    "\t {} of {} source files are compiled in {} unity batches",
+                    batched,
+                    total_srcs,
+                    self.unity_batches.len()
+                ),
             );
+        } else {
             log(
                 LogLevel::Log,
                 &format!(
@@ -255,170 +485,245 @@ impl<'a> Target<'a> {
                     srcs_needed, total_srcs
                 ),
             );
-            if !Path::new(OBJ_DIR).exists() {
-                fs::create_dir(OBJ_DIR).unwrap_or_else(|why| {
-                    log(
-                        LogLevel::Error,
-                        &format!("Couldn't create obj dir: {}", why),
-                    );
-                });
-            }
-        } else {
-            log(
-                LogLevel::Log,
-                &format!("Target: {} is up to date", &self.target_config.name),
-            );
-            return;
+        }
+        for (src, _) in &srcs_to_build {
+            let obj_dir = Path::new(&src.obj_name).parent().unwrap();
+            fs::create_dir_all(obj_dir).unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("Couldn't create obj dir: {}", why),
+                );
+                std::process::exit(1);
+            });
         }
         let progress_bar = Arc::new(Mutex::new(ProgressBar::new(srcs_needed as u64)));
 
         let num_complete = Arc::new(Mutex::new(0));
-        let src_hash_to_update = Arc::new(Mutex::new(Vec::new()));
         let warns = Arc::new(Mutex::new(Vec::new()));
-        self.srcs.par_iter().for_each(|src| {
-            let (to_build, _message) = src.to_build(&self.path_hash);
-            log(LogLevel::Debug, &format!("{}: {}", src.path, to_build));
-            if to_build {
-                let warn = src.build(self.build_config, self.target_config, &self.dependant_libs);
-                if warn.is_some() {
-                    warns.lock().unwrap().push(warn.unwrap());
-                }
-                src_hash_to_update.lock().unwrap().push(src);
-                log(LogLevel::Info, &format!("Compiled: {}", src.path));
-                let log_level = std::env::var("BUILDER_CPP_LOG_LEVEL").unwrap_or("".to_string());
-                if !(log_level == "Info" || log_level == "Debug") {
-                    let mut num_complete = num_complete.lock().unwrap();
-                    *num_complete += 1;
-                    let progress_bar = progress_bar.lock().unwrap();
-                    let template = format!(
-                        "    {}{}",
-                        "Compiling :".cyan(),
-                        "[{bar:40.}] {pos}/{len} ({percent}%) {msg}[{elapsed_precise}] "
-                    );
-                    progress_bar.set_style(
-                        ProgressStyle::with_template(&template)
-                            .unwrap()
-                            .progress_chars("=>-"),
-                    );
-                    progress_bar.inc(1);
-                }
+        srcs_to_build.par_iter().for_each(|(src, cmd)| {
+            let warn = src.build(cmd, true);
+            if let Some(warn) = warn {
+                warns.lock().unwrap().push(warn);
+            }
+            log(LogLevel::Info, &format!("Compiled: {}", src.path));
+            let log_level = std::env::var("BUILDER_CPP_LOG_LEVEL").unwrap_or("".to_string());
+            if !(log_level == "Info" || log_level == "Debug") {
+                let mut num_complete = num_complete.lock().unwrap();
+                *num_complete += 1;
+                let progress_bar = progress_bar.lock().unwrap();
+                let template = format!(
+                    "    {}{}",
+                    "Compiling :".cyan(),
+                    "[{bar:40.}] {pos}/{len} ({percent}%) {msg}[{elapsed_precise}] "
+                );
+                progress_bar.set_style(
+                    ProgressStyle::with_template(&template)
+                        .unwrap()
+                        .progress_chars("=>-"),
+                );
+                progress_bar.inc(1);
             }
         });
         let warns = warns.lock().unwrap();
-        if warns.len() > 0 {
+        if !warns.is_empty() {
             log(LogLevel::Warn, "Warnings emitted during build:");
             for warn in warns.iter() {
                 log(LogLevel::Warn, &format!("\t{}", warn));
             }
         }
 
-        for src in src_hash_to_update.lock().unwrap().iter() {
-            hasher::save_hash(&src.path, &mut self.path_hash);
-        }
-        if to_link {
-            log(LogLevel::Log, "Linking: Since source files were compiled");
-            for src in link_causer {
-                log(LogLevel::Info, &format!("\tFile: {}", &src));
+        for (src, cmd) in &srcs_to_build {
+            log(LogLevel::Info, &format!("\tFile: {}", &src.path));
+            hasher::clear_hashes(&src.obj_name, &mut path_hash);
+            hasher::save_hash(&src.obj_name, &src.path, &mut path_hash);
+            hasher::save_cmd_hash(&src.obj_name, &utils::join_args(cmd), &mut path_hash);
+            //the depfile was just written by the compiler
+            for include in src.get_dependant_includes().unwrap_or_default() {
+                hasher::save_hash(&src.obj_name, &include, &mut path_hash);
             }
-            for src in &self.srcs {
-                for include in &src.dependant_includes {
-                    hasher::save_hash(include, &mut self.path_hash);
-                }
+            if let Some(pch) = self.pch_of(src) {
+                hasher::save_hash(&src.obj_name, &pch.obj_name, &mut path_hash);
             }
-            hasher::save_hashes_to_file(&self.hash_file_path, &self.path_hash);
-            self.link(&self.dependant_libs);
         }
+        true
     }
 
-    /// Links the target
-    /// # Arguments
-    /// * `dep_targets` - The targets that this target depends on
-    pub fn link(&self, dep_targets: &Vec<Target>) {
-        let mut objs = Vec::new();
-        if !Path::new(BUILD_DIR).exists() {
-            let cmd = format!("mkdir -p {}", BUILD_DIR);
-            let output = Command::new("sh")
-                .arg("-c")
-                .arg(cmd)
-                .output()
-                .expect("failed to execute process");
-            if !output.status.success() {
-                log(
-                    LogLevel::Error,
-                    &format!(
-                        "Couldn't create build dir: {}",
-                        String::from_utf8_lossy(&output.stderr)
-                    ),
-                );
+    //returns true if a source, which may be a unity batch, has to be compiled with the given command
+    fn needs_build(&self, src: &Src, cmd: &[String], path_hash: &HashStore) -> bool {
+        let (mut to_build, mut message) = src.to_build(path_hash, &utils::join_args(cmd));
+        if let Some(pch) = self.pch_of(src) {
+            if !to_build && hasher::is_file_changed(&src.obj_name, &pch.obj_name, path_hash) {
+                to_build = true;
+                message = format!("\tPrecompiled header has changed: {}", &pch.path);
             }
         }
-        for src in &self.srcs {
-            objs.push(&src.obj_name);
+        if to_build {
+            log(LogLevel::Info, &message);
         }
+        to_build
+    }
 
-        let mut cmd = String::new();
-        cmd.push_str(&self.build_config.compiler);
-        cmd.push_str(" -o ");
-        cmd.push_str(&self.bin_path);
-        if self.target_config.typ == "dll" {
-            cmd.push_str(" -shared ");
-        }
+    //returns the objects the target is linked from,
+    //the object of a linked unity batch replaces the objects of the sources it includes
+    fn objects(&self) -> Vec<String> {
+        let linked_batches = self.linked_batches.lock().unwrap();
+        let batches = self.unity_batches.iter().zip(linked_batches.iter());
+        let batched: Vec<usize> = batches
+            .clone()
+            .filter(|(_, linked)| **linked)
+            .flat_map(|((_, members), _)| members.iter().copied())
+            .collect();
+        let mut objs: Vec<String> = (0..self.srcs.len())
+            .filter(|x| !batched.contains(x))
+            .map(|x| self.srcs[x].obj_name.clone())
+            .collect();
+        objs.extend(
+            batches
+                .filter(|(_, linked)| **linked)
+                .map(|((batch, _), _)| batch.obj_name.clone()),
+        );
+        objs
+    }
 
-        for obj in objs {
-            cmd.push_str(" ");
-            cmd.push_str(obj);
+    //precompiles the header of the target if it changed, returns true if it was precompiled
+    fn build_pch(&self, pch: &Src, path_hash: &mut HashStore) -> bool {
+        let cmd = self.pch_cmd(pch);
+        let (to_build, message) = pch.to_build(path_hash, &utils::join_args(&cmd));
+        if !to_build {
+            return false;
         }
-        cmd.push_str(" ");
-        cmd.push_str(&self.target_config.cflags);
-        cmd.push_str(" ");
-        for dep_target in dep_targets {
-            cmd.push_str(" -I");
-            cmd.push_str(&dep_target.target_config.include_dir);
-            cmd.push_str(" ");
-
-            let lib_name = dep_target.target_config.name.clone();
-            let lib_name = lib_name.replace("lib", "-l");
-            // cmd.push_str(&lib_name); // kind questionable too?
-            cmd.push_str(" ");
+        log(LogLevel::Info, &message);
+        log(
+            LogLevel::Log,
+            &format!(
+                "Precompiling header: {} of target: {}",
+                pch.path, self.target_config.name
+            ),
+        );
+        let stem = Target::pch_include(pch);
+        utils::create_dir(&Path::new(&stem).parent().unwrap().to_string_lossy());
+        //the header next to the precompiled one includes the real one,
+        //it is what gets precompiled and what the compiler falls back to if it cannot use it
+        let header = fs::canonicalize(&pch.path).unwrap_or_else(|_| PathBuf::from(&pch.path));
+        let forward = format!(
+            "#include \"{}\"\n",
+            header.to_string_lossy().replace('\\', "/")
+        );
+        if let Err(why) = fs::write(&stem, forward) {
+            log(
+                LogLevel::Error,
+                &format!("Couldn't write header: {}: {}", stem, why),
+            );
+            std::process::exit(1);
         }
+        if let Some(warn) = pch.build(&cmd, false) {
+            log(
+                LogLevel::Warn,
+                &format!("Warnings emitted while precompiling: {}", pch.path),
+            );
+            log(LogLevel::Warn, &format!("\t{}", warn));
+        }
+        hasher::clear_hashes(&pch.obj_name, path_hash);
+        hasher::save_hash(&pch.obj_name, &pch.path, path_hash);
+        hasher::save_cmd_hash(&pch.obj_name, &utils::join_args(&cmd), path_hash);
+        for include in pch.get_dependant_includes().unwrap_or_default() {
+            hasher::save_hash(&pch.obj_name, &include, path_hash);
+        }
+        true
+    }
 
-        for package in self.packages {
-            for target in &package.target_configs {
-                cmd.push_str(" -I");
-                cmd.push_str(&target.include_dir);
-                cmd.push_str(" ");
-
-                let lib_name = target.name.clone();
-                let lib_name = lib_name.replace("lib", "-l");
-                // cmd.push_str(&lib_name); // linker error probably from here
-                cmd.push_str(" ");
+    /// Links the target if its sources were compiled or the static libs it links changed
+    /// The targets it depends on have to be linked already
+    /// # Arguments
+    /// * `srcs_compiled` - Whether any source of the target was compiled
+    pub fn link_if_needed(&self, srcs_compiled: bool) {
+        //static libs are copied into the binary, so it has to be relinked when they change
+        let static_libs: Vec<String> = self
+            .get_link_deps()
+            .iter()
+            .filter(|x| x.target_config.typ == "lib")
+            .map(|x| x.bin_path.clone())
+            .collect();
+        let mut path_hash = self.path_hash.lock().unwrap();
+        let mut changed_libs = Vec::new();
+        for static_lib in &static_libs {
+            if hasher::is_file_changed(&self.bin_path, static_lib, &path_hash) {
+                changed_libs.push(static_lib);
+            }
+        }
+        let link_cmd = utils::join_args(&self.link_cmd());
+        let cmd_changed = hasher::is_cmd_changed(&self.bin_path, &link_cmd, &path_hash);
+        if !srcs_compiled && changed_libs.is_empty() && !cmd_changed {
+            log(
+                LogLevel::Log,
+                &format!("Target: {} is up to date", &self.target_config.name),
+            );
+            if hasher::refresh_hashes(&self.bin_path, &mut path_hash) {
+                hasher::save_hashes_to_file(&self.hash_file_path, &path_hash);
             }
+            return;
         }
 
-        if self.packages.len() + self.dependant_libs.len() > 0 {
-            cmd.push_str("-L");
-            cmd.push_str(BUILD_DIR);
-            cmd.push_str(" -Wl,-rpath,\'$ORIGIN\' ");
-            cmd.push_str(" ");
+        if srcs_compiled || !changed_libs.is_empty() {
+            log(
+                LogLevel::Log,
+                &format!(
+                    "Linking: {} since source files or static libs changed",
+                    &self.target_config.name
+                ),
+            );
+        } else {
+            log(
+                LogLevel::Log,
+                &format!(
+                    "Linking: {} since the link command changed",
+                    &self.target_config.name
+                ),
+            );
+        }
+        for static_lib in changed_libs {
+            log(LogLevel::Info, &format!("\tFile: {}", &static_lib));
+        }
+        hasher::clear_hashes(&self.bin_path, &mut path_hash);
+        for static_lib in &static_libs {
+            hasher::save_hash(&self.bin_path, static_lib, &mut path_hash);
+        }
+        hasher::save_cmd_hash(&self.bin_path, &link_cmd, &mut path_hash);
+        drop(path_hash);
+        self.link();
+    }
+
+    /// Links the target against its objects and the targets it depends on
+    pub fn link(&self) {
+        let objs = self.objects();
+        utils::create_dir(&profiles::build_dir());
+
+        if self.target_config.typ == "lib" {
+            self.archive(&objs);
+            return;
         }
-        cmd.push_str(&self.target_config.libs);
 
+        let cmd = self.link_cmd();
         log(
             LogLevel::Info,
             &format!("Linking target: {}", &self.target_config.name),
         );
-        log(LogLevel::Info, &format!("  Command: {}", &cmd));
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&cmd)
-            .output()
-            .expect("failed to execute process");
+        log(
+            LogLevel::Info,
+            &format!("  Command: {}", utils::join_args(&cmd)),
+        );
+        let job = jobs::acquire();
+        let output = run_cmd(&cmd);
+        drop(job);
         if output.status.success() {
             log(LogLevel::Info, "  Linking successful");
-            hasher::save_hashes_to_file(&self.hash_file_path, &self.path_hash);
+            hasher::save_hashes_to_file(&self.hash_file_path, &self.path_hash.lock().unwrap());
         } else {
             log(LogLevel::Error, "  Linking failed");
-            log(LogLevel::Error, &format!("  Command: {}", &cmd));
+            log(
+                LogLevel::Error,
+                &format!("  Command: {}", utils::join_args(&cmd)),
+            );
             log(
                 LogLevel::Error,
                 &format!("  Error: {}", String::from_utf8_lossy(&output.stderr)),
@@ -427,92 +732,243 @@ impl<'a> Target<'a> {
         }
     }
 
-    /// Generates the compile_commands.json file for a Src
-    fn gen_cc(&self, src: &Src) -> String {
-        let mut cc = String::new();
-        cc.push_str("{\n");
-        if self.build_config.compiler == "clang++" || self.build_config.compiler == "g++" {
-            cc.push_str("\t\"command\": \"c++");
-        } else if self.build_config.compiler == "clang" || self.build_config.compiler == "gcc" {
-            cc.push_str("\t\"command\": \"cc");
-        } else {
-            log(
-                LogLevel::Error,
-                &format!("Compiler: {} is not supported", &self.build_config.compiler),
-            );
-            log(
-                LogLevel::Error,
-                "Supported compilers: clang++, g++, clang, gcc",
-            );
-            std::process::exit(1);
+    //returns the arguments of the fully expanded command that links the target
+    //static libs are archived instead, so their command lists the archived objects
+    fn link_cmd(&self) -> Vec<String> {
+        let objs = self.objects();
+        if self.target_config.typ == "lib" {
+            let ar = toolchain::toolchain().ar.clone();
+            let mut cmd = vec![ar, "rc".to_string(), self.bin_path.clone()];
+            cmd.extend(objs);
+            return cmd;
         }
-        cc.push_str(" -c -o ");
-        cc.push_str(&src.obj_name);
-        cc.push_str(" -I");
-        cc.push_str(&self.target_config.include_dir);
 
-        for lib in &self.dependant_libs {
-            cc.push_str(" -I");
-            cc.push_str(&lib.target_config.include_dir);
+        let mut cmd = self.linker.clone();
+        cmd.push("-o".to_string());
+        cmd.push(self.bin_path.clone());
+        if self.target_config.typ == "dll" {
+            cmd.push("-shared".to_string());
         }
-        for pack in self.packages {
-            for tgtg in &pack.target_configs {
-                cc.push_str(" -I");
-                cc.push_str(&tgtg.include_dir);
+        cmd.extend(objs);
+        cmd.extend(self.link_flags.iter().cloned());
+
+        for package in self.packages {
+            for target in &package.target_configs {
+                cmd.push(format!("-I{}", target.include_dir));
             }
         }
 
-        cc.push_str(" ");
-        let cflags = &self.target_config.cflags;
+        if self.packages.len() + self.dependant_libs.len() > 0 {
+            let build_dir = profiles::build_dir();
+            cmd.push(format!("-L{}", build_dir));
+            cmd.push("-Wl,-rpath,$ORIGIN".to_string());
+            //lets the linker find the dlls that private deps of dll deps need
+            if !toolchain::toolchain().is_windows() {
+                cmd.push(format!("-Wl,-rpath-link,{}", build_dir));
+            }
+        }
 
-        let subcmds = cflags.split('`').collect::<Vec<&str>>();
-        // Take even entries are non-subcmds and odd entries are subcmds
-        let (subcmds, non_subcmds): (Vec<String>, String) = subcmds.iter().enumerate().fold(
-            (Vec::new(), String::new()),
-            |(mut subcmds, mut non_subcmds), (i, subcmd)| {
-                if i % 2 != 0 {
-                    subcmds.push(subcmd.to_string());
-                } else {
-                    non_subcmds.push_str(subcmd);
-                    non_subcmds.push_str(" ");
+        let link_deps = self.get_link_deps();
+        for dep_target in &link_deps {
+            match dep_target.target_config.typ.as_str() {
+                "lib" => cmd.push(dep_target.bin_path.clone()),
+                "dll" => {
+                    // the lib prefix is implied by -l, dependant lib names always start with it
+                    let name = &dep_target.target_config.name;
+                    cmd.push(format!("-l{}", name.strip_prefix("lib").unwrap_or(name)));
                 }
-                (subcmds, non_subcmds)
-            },
-        );
+                _ => {}
+            }
+        }
+        cmd.extend(self.libs.iter().cloned());
+        for link_lib in &self.target_config.link_libs {
+            cmd.push(flags::link_lib(link_lib));
+        }
+        //static and header only libs cannot carry their own libs, so they are passed on
+        for dep_target in &link_deps {
+            if dep_target.target_config.typ == "lib" || dep_target.target_config.typ == "hdr" {
+                cmd.extend(dep_target.libs.iter().cloned());
+                for link_lib in &dep_target.target_config.link_libs {
+                    cmd.push(flags::link_lib(link_lib));
+                }
+            }
+        }
+        cmd
+    }
 
-        cc.push_str(&non_subcmds);
-
-        for subcmd in subcmds {
-            let cmd_output = Command::new("sh")
-                .arg("-c")
-                .arg(&subcmd)
-                .output()
-                .expect("failed to execute process");
-            if cmd_output.status.success() {
-                let stdout = String::from_utf8_lossy(&cmd_output.stdout);
-                let stdout = stdout.replace("\n", " ");
-                cc.push_str(&stdout);
-            } else {
-                let stderr = String::from_utf8_lossy(&cmd_output.stderr);
+    /// Builds the given targets in parallel
+    /// The sources of all targets are compiled right away, and every target is linked
+    /// as soon as its own sources are compiled and the targets it depends on are linked
+    /// # Arguments
+    /// * `targets` - The targets to build, including every target they depend on
+    /// * `gen_cc` - Generate compile_commands.json
+    pub fn build_all(targets: &[Arc<Target<'a>>], gen_cc: bool) {
+        let index_of = |name: &str| targets.iter().position(|x| x.target_config.name == name);
+        let deps: Vec<Vec<usize>> = targets
+            .iter()
+            .map(|target| {
+                target
+                    .dependant_libs
+                    .iter()
+                    .filter_map(|x| index_of(&x.target_config.name))
+                    .unique()
+                    .collect()
+            })
+            .collect();
+        let dependents: Vec<Vec<usize>> = (0..targets.len())
+            .map(|index| (0..targets.len()).filter(|x| deps[*x].contains(&index)).collect())
+            .collect();
+        let schedule = Schedule {
+            targets,
+            dependents,
+            state: Mutex::new(ScheduleState {
+                deps_left: deps.iter().map(|x| x.len()).collect(),
+                srcs_compiled: vec![None; targets.len()],
+            }),
+        };
+        rayon::scope(|scope| {
+            for index in 0..targets.len() {
+                let schedule = &schedule;
+                scope.spawn(move |scope| {
+                    let srcs_compiled = schedule.targets[index].compile(gen_cc);
+                    let mut state = schedule.state.lock().unwrap();
+                    state.srcs_compiled[index] = Some(srcs_compiled);
+                    if state.deps_left[index] == 0 {
+                        drop(state);
+                        schedule.link(index, scope);
+                    }
+                });
+            }
+        });
+    }
+
+    //returns the deps this target links against, including the ones passed on
+    //by its deps, in link order where every library comes before the libraries it depends on
+    fn get_link_deps(&self) -> Vec<&Target<'a>> {
+        fn visit<'a, 'b>(target: &'b Target<'a>, visited: &mut Vec<&'b Target<'a>>) {
+            if visited
+                .iter()
+                .any(|x| x.target_config.name == target.target_config.name)
+            {
+                return;
+            }
+            for dep_target in target.get_passed_on_link_deps() {
+                visit(dep_target, visited);
+            }
+            visited.push(target);
+        }
+
+        let mut link_deps = Vec::new();
+        for (dep, dep_target) in self.target_config.deps.iter().zip(&self.dependant_libs) {
+            if dep.visibility != "interface" {
+                visit(dep_target, &mut link_deps);
+            }
+        }
+        link_deps.reverse();
+        link_deps
+    }
+
+    //returns the deps that targets linking against this target also have to link against
+    //a dll already links its private deps, everything else passes all of them on
+    fn get_passed_on_link_deps(&self) -> Vec<&Target<'a>> {
+        self.target_config
+            .deps
+            .iter()
+            .zip(&self.dependant_libs)
+            .filter(|(dep, _)| self.target_config.typ != "dll" || dep.visibility != "private")
+            .map(|(_, dep_target)| dep_target.as_ref())
+            .collect()
+    }
+
+    //archives the object files of a lib target into a static library
+    fn archive(&self, objs: &[String]) {
+        // ar only adds or replaces members, so stale objects would linger in an old archive
+        if Path::new(&self.bin_path).exists() {
+            fs::remove_file(&self.bin_path).unwrap_or_else(|why| {
                 log(
                     LogLevel::Error,
-                    &format!("Failed to execute subcmd: {}", &subcmd),
+                    &format!("Couldn't remove old archive {}: {}", &self.bin_path, why),
+                );
+                std::process::exit(1);
+            });
+        }
+
+        let toolchain = toolchain::toolchain();
+        let mut ar_cmd = Command::new(&toolchain.ar);
+        ar_cmd.arg("rc").arg(&self.bin_path).args(objs);
+        let mut ranlib_cmd = Command::new(&toolchain.ranlib);
+        ranlib_cmd.arg(&self.bin_path);
+
+        log(
+            LogLevel::Info,
+            &format!("Archiving target: {}", &self.target_config.name),
+        );
+        for mut cmd in [ar_cmd, ranlib_cmd] {
+            log(LogLevel::Info, &format!("  Command: {:?}", &cmd));
+            let job = jobs::acquire();
+            let output = cmd.output().unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("  Couldn't run {:?}: {}", cmd.get_program(), why),
+                );
+                std::process::exit(1);
+            });
+            drop(job);
+            if !output.status.success() {
+                log(LogLevel::Error, "  Archiving failed");
+                log(LogLevel::Error, &format!("  Command: {:?}", &cmd));
+                log(
+                    LogLevel::Error,
+                    &format!("  Error: {}", String::from_utf8_lossy(&output.stderr)),
                 );
-                log(LogLevel::Error, &format!("  Stderr: {}", stderr));
                 std::process::exit(1);
             }
         }
+        log(LogLevel::Info, "  Archiving successful");
+        hasher::save_hashes_to_file(&self.hash_file_path, &self.path_hash.lock().unwrap());
+    }
 
-        #[cfg(target_os = "linux")]
-        if self.target_config.typ == "dll" {
-            cc.push_str("-fPIC ");
+    /// Generates the compile_commands.json file for a Src
+    fn gen_cc(&self, src: &Src) -> String {
+        //nasm takes flags of its own, so it is listed with the command it is compiled with
+        let mut cmd = if src.language.is_nasm() {
+            self.compile_cmd(src)
+        } else {
+            self.compilers[&src.language.name].clone()
+        };
+        //launchers are for building only, tools reading this want the compiler itself
+        while cmd.len() > 1 && KNOWN_LAUNCHERS.contains(&program_name(&cmd[0]).as_str()) {
+            cmd.remove(0);
         }
-        #[cfg(target_os = "android")]
-        if self.target_config.typ == "dll" {
-            cc.push_str("-fPIC ");
+        if !src.language.is_nasm() {
+            //the usual drivers are listed under their generic names
+            if let Some(program) = cmd.first_mut() {
+                match program.as_str() {
+                    "clang++" | "g++" => *program = "c++".to_string(),
+                    "clang" | "gcc" => *program = "cc".to_string(),
+                    _ => {}
+                }
+            }
+            cmd.push("-c".to_string());
+            cmd.push("-o".to_string());
+            cmd.push(src.obj_name.clone());
+            //the header itself is included, since tools reading this cannot use the precompiled one
+            if let Some(pch) = self.pch_of(src) {
+                cmd.push("-include".to_string());
+                cmd.push(pch.path.clone());
+            }
+            cmd.extend(self.compile_flags(&src.language));
+            cmd.push(src.path.clone());
         }
 
-        cc.push_str(&src.path);
+        let mut cc = String::new();
+        cc.push_str("{\n");
+        cc.push_str("\t\"command\": \"");
+        cc.push_str(
+            &utils::join_args(&cmd)
+                .replace('\\', "\\\\")
+                .replace('"', "\\\""),
+        );
         cc.push_str("\",\n");
         let mut dirent = String::new();
         dirent.push_str("\t\"directory\": \"");
@@ -525,7 +981,6 @@ impl<'a> Target<'a> {
         );
         dirent.push_str("\",\n");
         let dirent = dirent.replace("/", "\\\\").replace("\\\\.\\\\", "\\\\");
-        cc.push_str(&dirent);
         let mut fileent = String::new();
         fileent.push_str("\t\"file\": \"");
         fileent.push_str(
@@ -535,63 +990,292 @@ impl<'a> Target<'a> {
                 .unwrap()
                 .replace("\\", "/"),
         );
-        fileent.push_str("/");
+        fileent.push('/');
         fileent.push_str(&src.path);
-        fileent.push_str("\"");
+        fileent.push('"');
         let fileent = fileent.replace("/", "\\\\").replace("\\\\.\\\\", "\\\\");
 
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let (dirent, fileent) = (dirent.replace("\\\\", "/"), fileent.replace("\\\\", "/"));
+        cc.push_str(&dirent);
         cc.push_str(&fileent);
-
         cc.push_str("\n}");
-        #[cfg(target_os = "linux")]
-        return cc.replace("\\\\", "/");
-        #[cfg(target_os = "android")]
-        return cc.replace("\\\\", "/");
-        #[cfg(target_os = "windows")]
-        return cc;
-    }
-    //returns a vector of source files in the given root path
-    fn get_srcs(&mut self, root_path: &str, target_config: &'a TargetConfig) -> Vec<Src> {
-        let root_dir = PathBuf::from(root_path);
-        let mut srcs: Vec<Src> = Vec::new();
-        let root_entries = std::fs::read_dir(root_dir).unwrap_or_else(|_| {
+        cc
+    }
+
+    //returns the arguments of the fully expanded command that compiles the given source of the target
+    fn compile_cmd(&self, src: &Src) -> Vec<String> {
+        let mut cmd = self.compilers[&src.language.name].clone();
+        if src.language.is_nasm() {
+            //nasm has no -c and writes its depfile with -MD
+            cmd.push(src.path.clone());
+            cmd.push("-o".to_string());
+            cmd.push(src.obj_name.clone());
+            cmd.push("-MD".to_string());
+            cmd.push(src.dep_path.clone());
+            for include_dir in &self.include_dirs {
+                //nasm only finds includes in a dir given with a trailing slash
+                cmd.push(format!("-I{}/", include_dir.trim_end_matches('/')));
+            }
+            for define in &self.defines {
+                cmd.push(format!("-D{}", define));
+            }
+            cmd.extend(self.language_flags[&src.language.name].iter().cloned());
+            return cmd;
+        }
+        cmd.push("-c".to_string());
+        cmd.push(src.path.clone());
+        cmd.push("-o".to_string());
+        cmd.push(src.obj_name.clone());
+        cmd.push("-MMD".to_string());
+        cmd.push("-MF".to_string());
+        cmd.push(src.dep_path.clone());
+        if let Some(pch) = self.pch_of(src) {
+            cmd.push("-include".to_string());
+            cmd.push(Target::pch_include(pch));
+        }
+        cmd.extend(self.compile_flags(&src.language));
+        cmd
+    }
+
+    //returns the arguments of the command that precompiles the header of the target
+    //it is compiled with the same flags as the sources including it, or the compiler rejects it
+    //the header is compiled through the one including it next to the precompiled file,
+    //since gcc warns about #pragma once in the file it is given
+    fn pch_cmd(&self, pch: &Src) -> Vec<String> {
+        let mut cmd = self.compilers[&pch.language.name].clone();
+        let header_language = if pch.language.is_cxx() {
+            "c++-header"
+        } else {
+            "c-header"
+        };
+        cmd.push("-x".to_string());
+        cmd.push(header_language.to_string());
+        cmd.push(Target::pch_include(pch));
+        cmd.push("-o".to_string());
+        cmd.push(pch.obj_name.clone());
+        cmd.push("-MMD".to_string());
+        cmd.push("-MF".to_string());
+        cmd.push(pch.dep_path.clone());
+        cmd.extend(self.compile_flags(&pch.language));
+        cmd
+    }
+
+    //returns the precompiled header the given source includes, if any
+    fn pch_of(&self, src: &Src) -> Option<&Src> {
+        self.pch
+            .as_ref()
+            .filter(|pch| pch.language.name == src.language.name)
+    }
+
+    //returns the path passed to -include for a precompiled header,
+    //the compiler finds the precompiled file by adding its extension to it
+    fn pch_include(pch: &Src) -> String {
+        let (stem, _) = pch.obj_name.rsplit_once('.').unwrap();
+        stem.to_string()
+    }
+
+    //groups the c and c++ sources of the target that are not excluded into unity batches
+    //a batch of a single source would only compile it under another name, so there are none
+    fn new_unity_batches(&self) -> Vec<(Src, Vec<usize>)> {
+        let mut batches = Vec::new();
+        for language_name in ["cxx", "c"] {
+            let members: Vec<usize> = (0..self.srcs.len())
+                .filter(|x| self.srcs[*x].language.name == language_name)
+                .filter(|x| !self.target_config.is_unity_excluded(&self.srcs[*x].path))
+                .collect();
+            for chunk in members.chunks(self.target_config.unity_batch_size) {
+                if chunk.len() < 2 {
+                    continue;
+                }
+                let language = self.srcs[chunk[0]].language.clone();
+                let file_name = format!(
+                    "unity_{}.{}",
+                    batches.len(),
+                    language.extensions.first().map_or(language_name, |x| x)
+                );
+                let path = format!(
+                    "{}/{}/{}",
+                    profiles::unity_dir(),
+                    self.target_config.name,
+                    file_name
+                );
+                let obj_name = format!(
+                    "{}/{}/unity/{}.o",
+                    profiles::obj_dir(),
+                    self.target_config.name,
+                    file_name
+                );
+                let dep_path = format!("{}.d", obj_name.strip_suffix(".o").unwrap());
+                let batch = Src::new(
+                    path,
+                    Target::get_src_name(&file_name),
+                    obj_name,
+                    dep_path,
+                    self.bin_path.clone(),
+                    language,
+                );
+                batches.push((batch, chunk.to_vec()));
+            }
+        }
+        batches
+    }
+
+    //writes the source of a unity batch, which includes the sources in it
+    //it is only written when they change, so that it is not compiled again otherwise
+    fn write_unity_batch(&self, batch: &Src, members: &[usize]) {
+        let mut contents = String::new();
+        for member in members {
+            let path = &self.srcs[*member].path;
+            let path = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+            contents.push_str(&format!(
+                "#include \"{}\"\n",
+                path.to_string_lossy().replace('\\', "/")
+            ));
+        }
+        if fs::read_to_string(&batch.path).is_ok_and(|x| x == contents) {
+            return;
+        }
+        utils::create_dir(&Path::new(&batch.path).parent().unwrap().to_string_lossy());
+        if let Err(why) = fs::write(&batch.path, contents) {
             log(
                 LogLevel::Error,
-                &format!("Could not read directory: {}", root_path),
+                &format!("Couldn't write unity batch: {}: {}", batch.path, why),
             );
             std::process::exit(1);
-        });
-        for entry in root_entries {
-            let entry = entry.unwrap();
-            if entry.path().is_dir() {
-                let path = entry.path().to_str().unwrap().to_string();
-                srcs.append(&mut self.get_srcs(&path, target_config));
-            } else {
-                if !entry.path().to_str().unwrap().ends_with(".cpp")
-                    && !entry.path().to_str().unwrap().ends_with(".c")
-                {
-                    continue;
-                }
-                let path = entry
-                    .path()
-                    .to_str()
+        }
+    }
+
+    //creates the precompiled header of the target, which is used by its sources of the language
+    //of the header, c++ if the target has c++ sources and c otherwise
+    fn new_pch(&self, path: &str) -> Src {
+        let origin = self.target_config.origin("pch");
+        if !Path::new(path).is_file() {
+            log(LogLevel::Error, &format!("Could not find pch: {}", path));
+            log(LogLevel::Error, &format!("  In {}", origin));
+            std::process::exit(1);
+        }
+        let language = ["cxx", "c"]
+            .iter()
+            .find_map(|name| self.srcs.iter().find(|x| x.language.name == *name))
+            .map(|x| x.language.clone())
+            .unwrap_or_else(|| {
+                log(
+                    LogLevel::Error,
+                    "A pch can only be used by targets with c or c++ sources",
+                );
+                log(LogLevel::Error, &format!("  In {}", origin));
+                std::process::exit(1);
+            });
+        let compiler = self.build_config.compiler_of(&language);
+        let obj_name = format!(
+            "{}/{}/{}.{}",
+            profiles::obj_dir(),
+            self.target_config.name,
+            Target::get_src_rel_path(path),
+            flags::pch_extension(compiler)
+        );
+        let dep_path = format!("{}.d", obj_name);
+        Src::new(
+            path.to_string(),
+            Target::get_src_name(path),
+            obj_name.clone(),
+            dep_path,
+            obj_name,
+            language,
+        )
+    }
+
+    //returns the flags of a source that go into both its compile command and compile_commands.json
+    fn compile_flags(&self, language: &Language) -> Vec<String> {
+        let mut compile_flags = Vec::new();
+        for include_dir in &self.include_dirs {
+            compile_flags.push(flags::include_dir(include_dir));
+        }
+        for define in &self.defines {
+            compile_flags.push(flags::define(define));
+        }
+        for package in &self.build_config.packages {
+            let include_dir = format!(
+                ".bld_cpp/includes/{}",
+                &package
+                    .split_whitespace()
+                    .next()
+                    .unwrap()
+                    .split('/')
+                    .next_back()
                     .unwrap()
-                    .to_string()
-                    .replace("\\", "/");
-                self.add_src(path);
+                    .replace(",", "")
+            );
+            compile_flags.push(flags::include_dir(&include_dir));
+        }
+        compile_flags.extend(self.system_cflags.iter().cloned());
+        compile_flags.extend(self.option_flags(language));
+        compile_flags.extend(self.cflags.iter().cloned());
+        if language.is_cxx() {
+            compile_flags.extend(self.cxxflags.iter().cloned());
+        }
+        compile_flags.extend(self.language_flags[&language.name].iter().cloned());
+        if self.target_config.typ == "dll" {
+            compile_flags.push("-fPIC".to_string());
+        }
+        compile_flags
+    }
+
+    //returns the flags for the warnings, std and optimization of the target
+    //that apply to sources of the given language
+    fn option_flags(&self, language: &Language) -> Vec<String> {
+        let mut option_flags = Vec::new();
+        if let Some(warnings) = &self.target_config.warnings {
+            option_flags.extend(flags::warnings(warnings).split_whitespace().map(String::from));
+        }
+        for std in &self.target_config.std {
+            if flags::std_applies(std, language) {
+                option_flags.push(flags::std(std));
             }
         }
-        srcs
+        if let Some(optimization) = &self.optimization {
+            option_flags.extend(flags::optimization(optimization).split_whitespace().map(String::from));
+        }
+        option_flags
+    }
+
+    //returns true if the target or a static lib linked into it has c++ sources
+    fn has_cxx_objects(&self) -> bool {
+        self.srcs.iter().any(|x| x.language.is_cxx())
+            || self
+                .get_link_deps()
+                .iter()
+                .filter(|x| x.target_config.typ == "lib")
+                .any(|x| x.srcs.iter().any(|y| y.language.is_cxx()))
+    }
+
+    //creates the directory of the hash file, which is the directory of the profile
+    fn create_hash_dir(hash_file_path: &str) {
+        let hash_dir = Path::new(hash_file_path).parent().unwrap();
+        if !hash_dir.exists() {
+            fs::create_dir_all(hash_dir).unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("Couldn't create {} directory: {}", hash_dir.display(), why),
+                );
+                std::process::exit(1);
+            });
+        }
     }
 
     //adds a source file to the target
     fn add_src(&mut self, path: String) {
         let name = Target::get_src_name(&path);
-        let obj_name = self.get_src_obj_name(&name);
-        let dependant_includes = self.get_dependant_includes(&path);
+        let obj_name = self.get_src_obj_name(&path);
+        let dep_path = format!("{}.d", obj_name.strip_suffix(".o").unwrap());
         let bin_path = self.bin_path.clone();
+        //only files of a known language are collected as sources
+        let language = Language::of_path(&self.build_config.languages, &path)
+            .unwrap()
+            .clone();
         self.srcs
-            .push(Src::new(path, name, obj_name, bin_path, dependant_includes));
+            .push(Src::new(path, name, obj_name, dep_path, bin_path, language));
     }
 
     //returns the file name without the extension from the path
@@ -603,70 +1287,32 @@ impl<'a> Target<'a> {
     }
 
     //return the object file name for the given source file
-    fn get_src_obj_name(&self, src_name: &str) -> String {
+    //objects mirror the source tree, so sources with the same name never share an object
+    fn get_src_obj_name(&self, path: &str) -> String {
         let mut obj_name = String::new();
-        obj_name.push_str(OBJ_DIR);
-        obj_name.push_str("/");
+        obj_name.push_str(&profiles::obj_dir());
+        obj_name.push('/');
         obj_name.push_str(&self.target_config.name);
-        obj_name.push_str(&src_name);
+        obj_name.push('/');
+        obj_name.push_str(&Target::get_src_rel_path(path));
         obj_name.push_str(".o");
         obj_name
     }
 
-    //returns a vector of .h or .hpp files the given C/C++ depends on
-    fn get_dependant_includes(&mut self, path: &str) -> Vec<String> {
-        let mut result = Vec::new();
-        let include_substrings = self.get_include_substrings(path).unwrap_or_else(|| {
-            log(
-                LogLevel::Error,
-                &format!("Failed to get include substrings for file: {}", path),
-            );
-            log(
-                LogLevel::Error,
-                &format!(
-                    "File included from: {:?}",
-                    self.dependant_includes.get(path)
-                ),
-            );
-            std::process::exit(1);
-        });
-        if include_substrings.len() == 0 {
-            return result;
-        }
-        for include_substring in include_substrings {
-            let dep_path = format!("{}/{}", &self.target_config.include_dir, &include_substring);
-            if self.dependant_includes.contains_key(&dep_path) {
-                continue;
-            }
-            result.append(&mut self.get_dependant_includes(&dep_path));
-            result.push(dep_path);
-            self.dependant_includes
-                .insert(include_substring, result.clone());
-        }
-        let result = result.into_iter().unique().collect();
-        result
-    }
-
-    //returns a vector of strings that are the include substrings
-    //of the given C/C++ file as variaible path
-    fn get_include_substrings(&self, path: &str) -> Option<Vec<String>> {
-        let file = std::fs::File::open(path);
-        if file.is_err() {
-            return None;
-        }
-        let mut file = file.unwrap();
-        let mut buf = String::new();
-        file.read_to_string(&mut buf).unwrap();
-
-        let mut lines = buf.lines();
-        let mut include_substrings = Vec::new();
-        while let Some(line) = lines.next() {
-            if line.starts_with("#include \"") {
-                let include_path = line.split("\"").nth(1).unwrap().to_owned();
-                include_substrings.push(include_path);
-            }
-        }
-        Some(include_substrings)
+    //returns the path of the source relative to the project, to be used inside the obj dir
+    //. is dropped and .. becomes __, so objects never end up outside of the obj dir
+    fn get_src_rel_path(path: &str) -> String {
+        let path = Path::new(path);
+        let current_dir = std::env::current_dir().unwrap();
+        let path = path.strip_prefix(&current_dir).unwrap_or(path);
+        path.components()
+            .filter_map(|component| match component {
+                Component::Normal(x) => Some(x.to_string_lossy().to_string()),
+                Component::ParentDir => Some("__".to_string()),
+                Component::Prefix(x) => Some(x.as_os_str().to_string_lossy().replace(':', "")),
+                Component::CurDir | Component::RootDir => None,
+            })
+            .join("/")
     }
 }
 
@@ -676,33 +1322,61 @@ impl Src {
         path: String,
         name: String,
         obj_name: String,
+        dep_path: String,
         bin_path: String,
-        dependant_includes: Vec<String>,
+        language: Language,
     ) -> Self {
         Self {
             path,
             name,
             obj_name,
+            dep_path,
             bin_path,
-            dependant_includes,
+            language,
         }
     }
 
     //returns a tuple of a bool and a string
     //the bool is true if the source file needs to be built
     //the string is the reason the source file needs to be built
-    fn to_build(&self, path_hash: &HashMap<String, String>) -> (bool, String) {
+    fn to_build(&self, path_hash: &HashStore, cmd: &str) -> (bool, String) {
         if !Path::new(&self.bin_path).exists() {
             let result = (true, format!("\tBinary does not exist: {}", &self.bin_path));
             return result;
         }
 
-        if hasher::is_file_changed(&self.path, &path_hash) {
+        if hasher::is_cmd_changed(&self.obj_name, cmd, path_hash) {
+            let result = (true, format!("\tCompile command has changed: {}", cmd));
+            return result;
+        }
+
+        if hasher::is_file_changed(&self.obj_name, &self.path, path_hash) {
             let result = (true, format!("\tSource file has changed: {}", &self.path));
             return result;
         }
-        for dependant_include in &self.dependant_includes {
-            if hasher::is_file_changed(&dependant_include.clone(), path_hash) {
+        //the compiler lists every header the source includes in the depfile
+        let dependant_includes = match self.get_dependant_includes() {
+            Some(dependant_includes) => dependant_includes,
+            None => {
+                let result = (
+                    true,
+                    format!("\tDependency file does not exist: {}", &self.dep_path),
+                );
+                return result;
+            }
+        };
+        for dependant_include in &dependant_includes {
+            if !Path::new(dependant_include).exists() {
+                let result = (
+                    true,
+                    format!(
+                        "\tSource file: {} depends on removed include file: {}",
+                        &self.path, &dependant_include
+                    ),
+                );
+                return result;
+            }
+            if hasher::is_file_changed(&self.obj_name, dependant_include, path_hash) {
                 let result = (
                     true,
                     format!(
@@ -720,75 +1394,70 @@ impl Src {
         result
     }
 
-    //builds the source file
-    fn build(
-        &self,
-        build_config: &BuildConfig,
-        target_config: &TargetConfig,
-        dependant_libs: &Vec<Target>,
-    ) -> Option<String> {
-        let mut cmd = String::new();
-        cmd.push_str(&build_config.compiler);
-        cmd.push_str(" -c ");
-        cmd.push_str(&self.path);
-        cmd.push_str(" -o ");
-        cmd.push_str(&self.obj_name);
-        cmd.push_str(" -I");
-        cmd.push_str(&target_config.include_dir);
-        cmd.push_str(" ");
-
-        for dependant_lib in dependant_libs {
-            cmd.push_str("-I");
-            cmd.push_str(dependant_lib.target_config.include_dir.as_str());
-            cmd.push_str(" ");
-        }
-
-        if build_config.packages.len() > 0 {
-            for package in &build_config.packages {
-                cmd.push_str("-I");
-                cmd.push_str(&format!(
-                    ".bld_cpp/includes/{} ",
-                    &package
-                        .split_whitespace()
-                        .into_iter()
-                        .next()
-                        .unwrap()
-                        .split('/')
-                        .last()
-                        .unwrap()
-                        .replace(",", "")
-                ));
-                cmd.push_str(" ");
+    //returns the headers listed in the depfile of the source file
+    //returns None if the source was not compiled with a depfile yet
+    fn get_dependant_includes(&self) -> Option<Vec<String>> {
+        let contents = std::fs::read_to_string(&self.dep_path).ok()?;
+        let includes = parse_depfile(&contents)
+            .into_iter()
+            .filter(|x| x.trim_start_matches("./") != self.path.trim_start_matches("./"))
+            .unique()
+            .collect();
+        Some(includes)
+    }
+
+    //builds the source file with the given compile command, run with the launcher if there is one
+    //nasm is never run with it, since launchers only know compilers of c and c++
+    //if cacheable, the object is restored from the object cache when it has it
+    fn build(&self, cmd: &[String], cacheable: bool) -> Option<String> {
+        //the launcher does not change the object, so it is not part of the key
+        let cache_key = cacheable.then(|| cache::key(cmd, &self.language)).flatten();
+        if let Some(key) = &cache_key {
+            if let Some(stderr) = cache::fetch(key, &self.obj_name, &self.dep_path) {
+                log(
+                    LogLevel::Info,
+                    &format!("Restored from cache: {}", &self.name),
+                );
+                return (!stderr.is_empty()).then_some(stderr);
             }
+            //the outputs may be hardlinks into the cache, which the compiler must not write through
+            let _ = fs::remove_file(&self.obj_name);
+            let _ = fs::remove_file(&self.dep_path);
         }
-
-        cmd.push_str(&target_config.cflags);
-
-        if target_config.typ == "dll" {
-            cmd.push_str(" -fPIC");
+        let mut cmd = cmd.to_vec();
+        if let Some(launcher) = LAUNCHER.get().filter(|_| !self.language.is_nasm()) {
+            cmd.splice(0..0, launcher.iter().cloned());
         }
-
+        let cmd = &cmd;
         log(LogLevel::Info, &format!("Building: {}", &self.name));
-        log(LogLevel::Info, &format!("  Command: {}", &cmd));
-        let output = Command::new("sh")
-            .arg("-c")
-            .arg(&cmd)
-            .output()
-            .expect("failed to execute process");
+        log(LogLevel::Info, &format!("  Command: {}", utils::join_args(cmd)));
+        let job = jobs::acquire();
+        let output = run_cmd(cmd);
+        drop(job);
         if output.status.success() {
             log(LogLevel::Info, &format!("  Success: {}", &self.name));
+            //sources that are not preprocessed, like plain .s files, get no depfile
+            if !Path::new(&self.dep_path).exists() {
+                let depfile = format!("{}: {}\n", self.obj_name, self.path.replace(' ', "\\ "));
+                if let Err(why) = fs::write(&self.dep_path, depfile) {
+                    log(LogLevel::Warn, &format!("Couldn't write depfile: {}", why));
+                }
+            }
             let stdout = String::from_utf8_lossy(&output.stdout);
-            if stdout.len() > 0 {
+            if !stdout.is_empty() {
                 log(LogLevel::Info, &format!("  Stdout: {}", stdout));
             }
             let stderr = String::from_utf8_lossy(&output.stderr);
-            if stderr.len() > 0 {
+            if let Some(key) = &cache_key {
+                cache::store(key, &self.obj_name, &self.dep_path, &stderr);
+            }
+            if !stderr.is_empty() {
                 return Some(stderr.to_string());
             }
-            return None;
+            None
         } else {
             log(LogLevel::Error, &format!("  Error: {}", &self.name));
-            log(LogLevel::Error, &format!("  Command: {}", &cmd));
+            log(LogLevel::Error, &format!("  Command: {}", utils::join_args(cmd)));
             log(
                 LogLevel::Error,
                 &format!("  Stdout: {}", String::from_utf8_lossy(&output.stdout)),
@@ -801,3 +1470,114 @@ impl Src {
         }
     }
 }
+
+//returns the name of a program without its directory and extension
+fn program_name(program: &str) -> String {
+    Path::new(program)
+        .file_stem()
+        .map(|x| x.to_string_lossy().to_lowercase())
+        .unwrap_or_default()
+}
+
+//runs a command given as its arguments, without a shell
+//compilers are checked to start with a program when the config is parsed, so cmd[0] is one
+fn run_cmd(cmd: &[String]) -> Output {
+    Command::new(&cmd[0]).args(&cmd[1..]).output().unwrap_or_else(|why| {
+        log(LogLevel::Error, &format!("Couldn't run {}: {}", &cmd[0], why));
+        std::process::exit(1);
+    })
+}
+
+//returns the prerequisites of the rules in a depfile written by -MMD
+//the depfile uses make syntax, so lines can be continued with a backslash
+//and spaces, # and $ in paths are escaped
+fn parse_depfile(contents: &str) -> Vec<String> {
+    let mut prerequisites = Vec::new();
+    //the words of the current rule start here, those before its colon are its targets
+    let mut rule_start = 0;
+    let mut word = String::new();
+    let mut chars = contents.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(' ') | Some('#') => word.push(chars.next().unwrap()),
+                Some('\n') => {
+                    chars.next();
+                    end_depfile_word(&mut word, &mut prerequisites);
+                }
+                Some('\r') => {
+                    chars.next();
+                    chars.next_if_eq(&'\n');
+                    end_depfile_word(&mut word, &mut prerequisites);
+                }
+                _ => word.push(c),
+            },
+            '$' if chars.peek() == Some(&'$') => word.push(chars.next().unwrap()),
+            //a colon followed by whitespace ends the targets of a rule,
+            //other colons are part of windows paths
+            ':' if word.is_empty()
+                || chars.peek().is_none()
+                || chars.peek().is_some_and(|x| x.is_whitespace()) =>
+            {
+                word.clear();
+                prerequisites.truncate(rule_start);
+            }
+            '\n' => {
+                end_depfile_word(&mut word, &mut prerequisites);
+                rule_start = prerequisites.len();
+            }
+            c if c.is_whitespace() => end_depfile_word(&mut word, &mut prerequisites),
+            _ => word.push(c),
+        }
+    }
+    end_depfile_word(&mut word, &mut prerequisites);
+    prerequisites
+}
+
+fn end_depfile_word(word: &mut String, prerequisites: &mut Vec<String>) {
+    if !word.is_empty() {
+        prerequisites.push(std::mem::take(word));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn depfile_escapes_are_read() {
+        let depfile = "obj/main.o: src/my\\ file.c include/a\\#b.h include/cost$$.h\n";
+        assert_eq!(
+            parse_depfile(depfile),
+            ["src/my file.c", "include/a#b.h", "include/cost$.h"]
+        );
+    }
+
+    #[test]
+    fn depfile_lines_are_continued() {
+        let depfile = "obj/main.o: src/main.c \\\n  include/a.h \\\r\n  include/b.h\n";
+        assert_eq!(
+            parse_depfile(depfile),
+            ["src/main.c", "include/a.h", "include/b.h"]
+        );
+    }
+
+    #[test]
+    fn depfile_drive_letters_are_part_of_paths() {
+        let depfile = "C:/obj/main.o: C:/src/main.c C:\\include\\a.h\n";
+        assert_eq!(
+            parse_depfile(depfile),
+            ["C:/src/main.c", "C:\\include\\a.h"]
+        );
+    }
+
+    #[test]
+    fn depfile_targets_of_every_rule_are_skipped() {
+        //-MP adds a rule without prerequisites for every header
+        let depfile = "obj/a.o obj/b.o: src/a.c include/a.h\ninclude/a.h:\nobj/c.o : src/c.c\n";
+        assert_eq!(
+            parse_depfile(depfile),
+            ["src/a.c", "include/a.h", "src/c.c"]
+        );
+    }
+}
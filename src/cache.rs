@@ -0,0 +1,765 @@
+//! This module caches compiled objects, so that sources compiled before, by any project, are
+//! not compiled again.
+//!
+//! An object is cached under a hash of its preprocessed source, the fully expanded command
+//! compiling it and the version of the compiler, so changing a header, a flag or the compiler
+//! misses the cache. The depfile and the warnings of the compiler are cached with the object.
+//! Entries are kept in a directory, `~/.cache/builder_cpp` by default, and the least recently
+//! used ones are evicted once the cache grows past its size limit.
+//! The cache is enabled with a `[cache]` table in the config.
+use crate::jobs;
+use crate::languages::Language;
+use crate::utils::{self, log, LogLevel};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{LazyLock, Mutex, OnceLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+use toml::{Table, Value};
+
+/// The size limit of the cache if the config sets none, 5 GiB
+pub const DEFAULT_MAX_SIZE: u64 = 5 << 30;
+
+//changed whenever what goes into a key or an entry changes, so that old entries are missed
+const KEY_VERSION: &str = "builder_cpp cache 1";
+
+static CACHE: OnceLock<Cache> = OnceLock::new();
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+//the output of --version of every compiler used in this build
+static COMPILER_VERSIONS: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+struct Cache {
+    backend: Box<dyn Backend>,
+    max_size: u64,
+}
+
+/// The cache settings of the config
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// False if the cache table sets enabled = false
+    pub enabled: bool,
+    /// The directory the cache is kept in
+    pub dir: String,
+    /// The size in bytes above which the least recently used entries are evicted
+    pub max_size: u64,
+    /// Hardlink objects into and out of the cache instead of copying them
+    pub hardlink: bool,
+}
+
+/// The number of times sources were found in the cache or not, over all builds
+/// Builds finishing at the same time may each add their counts to the same old ones, so the
+/// counts are approximate
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+/// An object in the cache
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub key: String,
+    /// The size in bytes of the object, its depfile and warnings
+    pub size: u64,
+    /// When the entry was last stored or restored
+    pub last_used: SystemTime,
+}
+
+/// Where the entries of the cache are kept
+pub trait Backend: Send + Sync {
+    /// Returns where the backend keeps the cache, to show to the user
+    fn location(&self) -> String;
+    /// Restores the object and depfile of an entry to the given paths
+    /// Returns the warnings of the compiler, or None if there is no such entry
+    fn fetch(&self, key: &str, obj: &Path, dep: &Path) -> Option<String>;
+    /// Stores an object, its depfile and the warnings of the compiler as an entry
+    fn store(&self, key: &str, obj: &Path, dep: &Path, stderr: &str) -> io::Result<()>;
+    /// Returns every entry in the cache
+    fn entries(&self) -> Vec<Entry>;
+    /// Removes an entry from the cache
+    fn remove(&self, key: &str) -> io::Result<()>;
+    /// Returns the hits and misses recorded so far
+    fn load_stats(&self) -> Stats;
+    /// Records the hits and misses
+    fn save_stats(&self, stats: &Stats) -> io::Result<()>;
+}
+
+/// A cache in a directory on the local file system
+/// Every entry is a directory named by its key, holding the files obj, dep and stderr
+pub struct LocalBackend {
+    dir: PathBuf,
+    hardlink: bool,
+}
+
+impl Default for CacheConfig {
+    fn default() -> CacheConfig {
+        CacheConfig {
+            enabled: true,
+            dir: CacheConfig::default_dir(),
+            max_size: DEFAULT_MAX_SIZE,
+            hardlink: false,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Parses the cache table of a config, there is no cache if the config has none
+    /// # Arguments
+    /// * `config` - The whole config file
+    pub fn parse(config: &Table) -> Option<CacheConfig> {
+        let cache_toml = match config.get("cache") {
+            Some(Value::Table(cache_toml)) => cache_toml,
+            Some(_) => {
+                log(LogLevel::Error, "cache is not a table");
+                std::process::exit(1);
+            }
+            None => return None,
+        };
+        let get_bool = |key: &str, default: bool| match cache_toml.get(key) {
+            Some(Value::Boolean(value)) => *value,
+            Some(_) => {
+                log(
+                    LogLevel::Error,
+                    &format!("{} of cache is not a boolean", key),
+                );
+                std::process::exit(1);
+            }
+            None => default,
+        };
+        let dir = match cache_toml.get("dir") {
+            Some(Value::String(dir)) => expand_home(dir),
+            Some(_) => {
+                log(LogLevel::Error, "dir of cache is not a string");
+                std::process::exit(1);
+            }
+            None => CacheConfig::default_dir(),
+        };
+        let max_size = match cache_toml.get("max_size") {
+            Some(max_size) => parse_size(max_size).unwrap_or_else(|| {
+                log(
+                    LogLevel::Error,
+                    "max_size of cache must be a number of bytes or a size like 500M or 5G",
+                );
+                std::process::exit(1);
+            }),
+            None => DEFAULT_MAX_SIZE,
+        };
+        Some(CacheConfig {
+            enabled: get_bool("enabled", true),
+            dir,
+            max_size,
+            hardlink: get_bool("hardlink", false),
+        })
+    }
+
+    /// Returns the directory the cache is kept in if the config sets none
+    /// This is builder_cpp in the cache directory of the user, like ~/.cache/builder_cpp
+    pub fn default_dir() -> String {
+        #[cfg(target_os = "windows")]
+        let base = std::env::var("LOCALAPPDATA").ok().filter(|x| !x.is_empty());
+        #[cfg(any(target_os = "linux", target_os = "android"))]
+        let base = std::env::var("XDG_CACHE_HOME")
+            .ok()
+            .filter(|x| !x.is_empty())
+            .or_else(|| home_dir().map(|x| format!("{}/.cache", x)));
+        match base {
+            Some(base) => format!("{}/builder_cpp", base),
+            None => "./.bld_cpp/cache".to_string(),
+        }
+    }
+}
+
+impl LocalBackend {
+    /// Creates a backend keeping the cache in a directory
+    /// # Arguments
+    /// * `dir` - The directory, which is created when the first entry is stored
+    /// * `hardlink` - Hardlink objects instead of copying them
+    pub fn new(dir: &str, hardlink: bool) -> LocalBackend {
+        LocalBackend {
+            dir: PathBuf::from(dir),
+            hardlink,
+        }
+    }
+
+    //entries are spread over directories named by the first two characters of their key
+    fn entry_dir(&self, key: &str) -> PathBuf {
+        self.dir.join(&key[..2]).join(key)
+    }
+
+    //places a copy of a file at dest, or a hardlink to it if enabled and possible
+    fn place(&self, src: &Path, dest: &Path) -> io::Result<()> {
+        //never write through a hardlink into the file it shares
+        if dest.exists() {
+            fs::remove_file(dest)?;
+        }
+        if self.hardlink && fs::hard_link(src, dest).is_ok() {
+            return Ok(());
+        }
+        fs::copy(src, dest).map(|_| ())
+    }
+}
+
+impl Backend for LocalBackend {
+    fn location(&self) -> String {
+        self.dir.to_string_lossy().to_string()
+    }
+
+    fn fetch(&self, key: &str, obj: &Path, dep: &Path) -> Option<String> {
+        let entry_dir = self.entry_dir(key);
+        let cached_obj = entry_dir.join("obj");
+        //entries are moved in place once complete, so an entry with an object has everything
+        if !cached_obj.exists() {
+            return None;
+        }
+        let stderr = fs::read_to_string(entry_dir.join("stderr")).ok()?;
+        self.place(&cached_obj, obj).ok()?;
+        self.place(&entry_dir.join("dep"), dep).ok()?;
+        //the modification time of the object is when the entry was last used
+        if let Ok(file) = File::options().write(true).open(&cached_obj) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(stderr)
+    }
+
+    fn store(&self, key: &str, obj: &Path, dep: &Path, stderr: &str) -> io::Result<()> {
+        let entry_dir = self.entry_dir(key);
+        if entry_dir.exists() {
+            return Ok(());
+        }
+        //the entry is written elsewhere first, so other builds never see half of it
+        let tmp_dir = self
+            .dir
+            .join("tmp")
+            .join(format!("{}.{}", key, std::process::id()));
+        let result = fs::create_dir_all(&tmp_dir)
+            .and_then(|_| self.place(obj, &tmp_dir.join("obj")))
+            .and_then(|_| fs::copy(dep, tmp_dir.join("dep")).map(|_| ()))
+            .and_then(|_| fs::write(tmp_dir.join("stderr"), stderr))
+            .and_then(|_| fs::create_dir_all(entry_dir.parent().unwrap()))
+            .and_then(|_| fs::rename(&tmp_dir, &entry_dir));
+        if result.is_err() {
+            let _ = fs::remove_dir_all(&tmp_dir);
+            //another build may have stored the same entry in the meantime
+            if entry_dir.exists() {
+                return Ok(());
+            }
+        }
+        result
+    }
+
+    fn entries(&self) -> Vec<Entry> {
+        let mut entries = Vec::new();
+        let Ok(shards) = fs::read_dir(&self.dir) else {
+            return entries;
+        };
+        for shard in shards.flatten() {
+            //skips tmp and the stats file
+            if shard.file_name().len() != 2 || !shard.path().is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(shard.path()).into_iter().flatten().flatten() {
+                let entry_dir = entry.path();
+                let size = fs::read_dir(&entry_dir)
+                    .into_iter()
+                    .flatten()
+                    .flatten()
+                    .filter_map(|x| x.metadata().ok())
+                    .map(|x| x.len())
+                    .sum();
+                let last_used = fs::metadata(entry_dir.join("obj"))
+                    .and_then(|x| x.modified())
+                    .unwrap_or(UNIX_EPOCH);
+                entries.push(Entry {
+                    key: entry.file_name().to_string_lossy().to_string(),
+                    size,
+                    last_used,
+                });
+            }
+        }
+        entries
+    }
+
+    fn remove(&self, key: &str) -> io::Result<()> {
+        fs::remove_dir_all(self.entry_dir(key))
+    }
+
+    fn load_stats(&self) -> Stats {
+        let stats_toml = fs::read_to_string(self.dir.join("stats.toml"))
+            .ok()
+            .and_then(|x| x.parse::<Table>().ok())
+            .unwrap_or_default();
+        let get = |key: &str| {
+            stats_toml
+                .get(key)
+                .and_then(|x| x.as_integer())
+                .and_then(|x| u64::try_from(x).ok())
+                .unwrap_or(0)
+        };
+        Stats {
+            hits: get("hits"),
+            misses: get("misses"),
+        }
+    }
+
+    fn save_stats(&self, stats: &Stats) -> io::Result<()> {
+        //written elsewhere first like entries, so a build reading the stats never sees half of them
+        let tmp_dir = self.dir.join("tmp");
+        let tmp_path = tmp_dir.join(format!("stats.{}.toml", std::process::id()));
+        fs::create_dir_all(&tmp_dir)?;
+        fs::write(
+            &tmp_path,
+            format!("hits = {}\nmisses = {}\n", stats.hits, stats.misses),
+        )?;
+        fs::rename(&tmp_path, self.dir.join("stats.toml")).inspect_err(|_| {
+            let _ = fs::remove_file(&tmp_path);
+        })
+    }
+}
+
+/// Enables the cache for this build
+/// Exits if the cache directory cannot be created
+/// # Arguments
+/// * `config` - The cache settings of the config
+pub fn set_cache(config: &CacheConfig) {
+    if !config.enabled {
+        return;
+    }
+    fs::create_dir_all(&config.dir).unwrap_or_else(|why| {
+        log(
+            LogLevel::Error,
+            &format!("Could not create cache directory: {}", config.dir),
+        );
+        log(LogLevel::Error, &format!("  Error: {}", why));
+        std::process::exit(1);
+    });
+    let cache = Cache {
+        backend: Box::new(LocalBackend::new(&config.dir, config.hardlink)),
+        max_size: config.max_size,
+    };
+    if CACHE.set(cache).is_err() {
+        log(LogLevel::Warn, "Cache already set");
+        return;
+    }
+    log(
+        LogLevel::Info,
+        &format!("Caching objects in: {}", config.dir),
+    );
+}
+
+/// Returns the key a source compiled with a command is cached under
+/// Returns None if the cache is disabled or the source cannot be cached, like nasm and
+/// assembly sources and those failing to preprocess
+/// # Arguments
+/// * `cmd` - The fully expanded compile command, without a launcher
+/// * `language` - The language of the source
+pub fn key(cmd: &[String], language: &Language) -> Option<String> {
+    CACHE.get()?;
+    if language.is_nasm() || language.name == "as" {
+        return None;
+    }
+    let job = jobs::acquire();
+    let output = Command::new(&cmd[0])
+        .args(preprocess_args(cmd))
+        .output()
+        .ok()?;
+    drop(job);
+    if !output.status.success() {
+        //the compiler reports the error when the source is compiled
+        return None;
+    }
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(KEY_VERSION.as_bytes());
+    hasher.update(compiler_version(&cmd[0]).as_bytes());
+    hasher.update(b"\0");
+    hasher.update(utils::join_args(cmd).as_bytes());
+    hasher.update(b"\0");
+    hasher.update(&output.stdout);
+    Some(hasher.finalize().to_hex().to_string())
+}
+
+/// Restores the object and depfile of a source from the cache
+/// Returns the warnings the compiler emitted for it, or None on a miss
+/// # Arguments
+/// * `key` - The key returned by key
+/// * `obj` - The path of the object
+/// * `dep` - The path of the depfile
+pub fn fetch(key: &str, obj: &str, dep: &str) -> Option<String> {
+    let cache = CACHE.get()?;
+    let stderr = cache.backend.fetch(key, Path::new(obj), Path::new(dep));
+    match stderr {
+        Some(_) => HITS.fetch_add(1, Ordering::Relaxed),
+        None => MISSES.fetch_add(1, Ordering::Relaxed),
+    };
+    stderr
+}
+
+/// Stores the object and depfile of a source that was just compiled in the cache
+/// # Arguments
+/// * `key` - The key returned by key
+/// * `obj` - The path of the object
+/// * `dep` - The path of the depfile
+/// * `stderr` - The warnings the compiler emitted
+pub fn store(key: &str, obj: &str, dep: &str, stderr: &str) {
+    let Some(cache) = CACHE.get() else {
+        return;
+    };
+    if let Err(why) = cache
+        .backend
+        .store(key, Path::new(obj), Path::new(dep), stderr)
+    {
+        log(
+            LogLevel::Warn,
+            &format!("Couldn't store {} in the cache: {}", obj, why),
+        );
+    }
+}
+
+/// Records the hits and misses of this build, and evicts the least recently used entries
+/// if the cache grew past its size limit
+pub fn finish_build() {
+    let Some(cache) = CACHE.get() else {
+        return;
+    };
+    let hits = HITS.load(Ordering::Relaxed);
+    let misses = MISSES.load(Ordering::Relaxed);
+    //builds that compiled nothing leave the cache as it was
+    if hits + misses == 0 {
+        return;
+    }
+    log(
+        LogLevel::Info,
+        &format!("Cache hits: {}, misses: {}", hits, misses),
+    );
+    let mut stats = cache.backend.load_stats();
+    stats.hits += hits;
+    stats.misses += misses;
+    if let Err(why) = cache.backend.save_stats(&stats) {
+        log(
+            LogLevel::Warn,
+            &format!("Couldn't save the cache stats: {}", why),
+        );
+    }
+    let evicted = evict(cache.backend.as_ref(), cache.max_size);
+    if evicted > 0 {
+        log(
+            LogLevel::Info,
+            &format!("Evicted {} objects from the cache", evicted),
+        );
+    }
+}
+
+/// Shows where the cache is, how large it is and how often it was hit
+/// # Arguments
+/// * `config` - The cache settings of the config
+pub fn print_stats(config: &CacheConfig) {
+    let backend = LocalBackend::new(&config.dir, config.hardlink);
+    let entries = backend.entries();
+    let size: u64 = entries.iter().map(|x| x.size).sum();
+    let stats = backend.load_stats();
+    log(
+        LogLevel::Log,
+        &format!("Cache directory: {}", backend.location()),
+    );
+    log(LogLevel::Log, &format!("  Objects: {}", entries.len()));
+    log(
+        LogLevel::Log,
+        &format!(
+            "  Size: {} of {}",
+            format_size(size),
+            format_size(config.max_size)
+        ),
+    );
+    log(LogLevel::Log, &format!("  Hits: {}", stats.hits));
+    log(LogLevel::Log, &format!("  Misses: {}", stats.misses));
+    if stats.hits + stats.misses > 0 {
+        log(
+            LogLevel::Log,
+            &format!(
+                "  Hit rate: {:.1}%",
+                stats.hits as f64 * 100.0 / (stats.hits + stats.misses) as f64
+            ),
+        );
+    }
+}
+
+/// Removes every entry from the cache and resets its stats
+/// # Arguments
+/// * `config` - The cache settings of the config
+pub fn clear(config: &CacheConfig) {
+    let backend = LocalBackend::new(&config.dir, config.hardlink);
+    let entries = backend.entries();
+    for entry in &entries {
+        if let Err(why) = backend.remove(&entry.key) {
+            log(
+                LogLevel::Error,
+                &format!("Could not remove {} from the cache: {}", entry.key, why),
+            );
+            std::process::exit(1);
+        }
+    }
+    if Path::new(&config.dir).exists() {
+        if let Err(why) = backend.save_stats(&Stats::default()) {
+            log(
+                LogLevel::Warn,
+                &format!("Couldn't reset the cache stats: {}", why),
+            );
+        }
+    }
+    log(
+        LogLevel::Log,
+        &format!("Removed {} objects from the cache", entries.len()),
+    );
+}
+
+//removes the least recently used entries until the cache is no larger than max_size,
+//returns how many were removed
+fn evict(backend: &dyn Backend, max_size: u64) -> usize {
+    let mut entries = backend.entries();
+    let mut size: u64 = entries.iter().map(|x| x.size).sum();
+    entries.sort_by_key(|x| x.last_used);
+    let mut evicted = 0;
+    for entry in entries {
+        if size <= max_size {
+            break;
+        }
+        if backend.remove(&entry.key).is_ok() {
+            size -= entry.size;
+            evicted += 1;
+        }
+    }
+    evicted
+}
+
+//returns the arguments after the compiler of a compile command that write the preprocessed
+//source to stdout instead of compiling it
+fn preprocess_args(cmd: &[String]) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut cmd_args = cmd[1..].iter();
+    while let Some(arg) = cmd_args.next() {
+        match arg.as_str() {
+            "-c" | "-MMD" | "-MD" | "-MP" => {}
+            "-o" | "-MF" | "-MT" | "-MQ" => {
+                cmd_args.next();
+            }
+            _ => args.push(arg.clone()),
+        }
+    }
+    args.push("-E".to_string());
+    args
+}
+
+//returns the output of --version of a compiler, asking each compiler once per build
+fn compiler_version(compiler: &str) -> String {
+    let mut versions = COMPILER_VERSIONS.lock().unwrap();
+    versions
+        .entry(compiler.to_string())
+        .or_insert_with(|| {
+            Command::new(compiler)
+                .arg("--version")
+                .output()
+                .map(|x| String::from_utf8_lossy(&x.stdout).to_string())
+                .unwrap_or_default()
+        })
+        .clone()
+}
+
+//parses a size like 500M, 1.5GiB or 5G, or a number of bytes
+fn parse_size(value: &Value) -> Option<u64> {
+    match value {
+        Value::Integer(size) => u64::try_from(*size).ok(),
+        Value::String(size) => {
+            let size = size.trim();
+            let number = size.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            let unit = size[number.len()..].to_ascii_uppercase();
+            let shift = match unit.trim_end_matches("IB").trim_end_matches('B') {
+                "" => 0,
+                "K" => 10,
+                "M" => 20,
+                "G" => 30,
+                "T" => 40,
+                _ => return None,
+            };
+            let number: f64 = number.trim().parse().ok()?;
+            (number.is_finite() && number >= 0.0).then(|| (number * (1u64 << shift) as f64) as u64)
+        }
+        _ => None,
+    }
+}
+
+//formats a number of bytes, like 1.5 GiB
+fn format_size(size: u64) -> String {
+    let units = ["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", size)
+    } else {
+        format!("{:.1} {}", value, units[unit])
+    }
+}
+
+//returns the home directory of the user
+fn home_dir() -> Option<String> {
+    #[cfg(target_os = "windows")]
+    let home = std::env::var("USERPROFILE");
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let home = std::env::var("HOME");
+    home.ok().filter(|x| !x.is_empty())
+}
+
+//replaces a leading ~ in a path with the home directory
+fn expand_home(path: &str) -> String {
+    match path.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with(['/', '\\']) => match home_dir() {
+            Some(home) => format!("{}{}", home, rest),
+            None => path.to_string(),
+        },
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    //a directory of its own for every test, removed before the test uses it
+    fn test_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("builder_cpp_cache_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    //stores an entry with an object of a size, last used some seconds after the epoch
+    fn store_entry(backend: &LocalBackend, dir: &Path, key: &str, size: usize, last_used: u64) {
+        let obj = dir.join(format!("{}.o", key));
+        let dep = dir.join(format!("{}.d", key));
+        fs::write(&obj, vec![0u8; size]).unwrap();
+        fs::write(&dep, "").unwrap();
+        backend.store(key, &obj, &dep, "").unwrap();
+        let cached_obj = backend.entry_dir(key).join("obj");
+        let file = File::options().write(true).open(cached_obj).unwrap();
+        file.set_modified(UNIX_EPOCH + Duration::from_secs(last_used))
+            .unwrap();
+    }
+
+    fn keys(backend: &LocalBackend) -> Vec<String> {
+        let mut keys: Vec<String> = backend.entries().into_iter().map(|x| x.key).collect();
+        keys.sort();
+        keys
+    }
+
+    fn args(cmd: &str) -> Vec<String> {
+        cmd.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn parse_size_takes_bytes_and_units() {
+        assert_eq!(parse_size(&Value::Integer(1024)), Some(1024));
+        assert_eq!(parse_size(&Value::String("500".into())), Some(500));
+        assert_eq!(parse_size(&Value::String("4K".into())), Some(4 << 10));
+        assert_eq!(parse_size(&Value::String("500M".into())), Some(500 << 20));
+        assert_eq!(parse_size(&Value::String("5G".into())), Some(5 << 30));
+        assert_eq!(parse_size(&Value::String("5gb".into())), Some(5 << 30));
+        assert_eq!(parse_size(&Value::String("1.5GiB".into())), Some(3 << 29));
+        assert_eq!(parse_size(&Value::String(" 2 T ".into())), Some(2 << 40));
+    }
+
+    #[test]
+    fn parse_size_rejects_invalid_sizes() {
+        assert_eq!(parse_size(&Value::Integer(-1)), None);
+        assert_eq!(parse_size(&Value::String("-1G".into())), None);
+        assert_eq!(parse_size(&Value::String("5X".into())), None);
+        assert_eq!(parse_size(&Value::String("G".into())), None);
+        assert_eq!(parse_size(&Value::String("inf".into())), None);
+        assert_eq!(parse_size(&Value::Boolean(true)), None);
+    }
+
+    #[test]
+    fn format_size_picks_the_largest_unit() {
+        assert_eq!(format_size(0), "0 B");
+        assert_eq!(format_size(1023), "1023 B");
+        assert_eq!(format_size(1024), "1.0 KiB");
+        assert_eq!(format_size(3 << 29), "1.5 GiB");
+        assert_eq!(format_size(2048 << 40), "2048.0 TiB");
+    }
+
+    #[test]
+    fn expand_home_only_expands_a_leading_tilde() {
+        if let Some(home) = home_dir() {
+            assert_eq!(expand_home("~"), home);
+            assert_eq!(expand_home("~/cache"), format!("{}/cache", home));
+        }
+        assert_eq!(expand_home("~user/cache"), "~user/cache");
+        assert_eq!(expand_home("/tmp/~/cache"), "/tmp/~/cache");
+        assert_eq!(expand_home("cache"), "cache");
+    }
+
+    #[test]
+    fn preprocess_args_drop_outputs_and_depfiles() {
+        let cmd = args("gcc -c ./src/a.c -o obj/a.c.o -MMD -MF obj/a.c.d -I./src -DX=1 -O2");
+        assert_eq!(
+            preprocess_args(&cmd),
+            args("./src/a.c -I./src -DX=1 -O2 -E")
+        );
+        let cmd = args("g++ -MD -MP -MT a.o -MQ b.o -c a.cpp");
+        assert_eq!(preprocess_args(&cmd), args("a.cpp -E"));
+    }
+
+    #[test]
+    fn evict_removes_the_least_recently_used_entries() {
+        let dir = test_dir("evict");
+        let backend = LocalBackend::new(&dir.join("cache").to_string_lossy(), false);
+        store_entry(&backend, &dir, "aa01", 100, 3);
+        store_entry(&backend, &dir, "bb02", 100, 1);
+        store_entry(&backend, &dir, "cc03", 100, 2);
+
+        assert_eq!(evict(&backend, 300), 0);
+        assert_eq!(keys(&backend), ["aa01", "bb02", "cc03"]);
+
+        assert_eq!(evict(&backend, 250), 1);
+        assert_eq!(keys(&backend), ["aa01", "cc03"]);
+
+        assert_eq!(evict(&backend, 0), 2);
+        assert!(keys(&backend).is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn a_restored_entry_is_used_last() {
+        let dir = test_dir("restore");
+        let backend = LocalBackend::new(&dir.join("cache").to_string_lossy(), false);
+        store_entry(&backend, &dir, "aa01", 100, 1);
+        store_entry(&backend, &dir, "bb02", 100, 2);
+        let stderr = backend.fetch("aa01", &dir.join("out.o"), &dir.join("out.d"));
+        assert_eq!(stderr.as_deref(), Some(""));
+
+        assert_eq!(evict(&backend, 100), 1);
+        assert_eq!(keys(&backend), ["aa01"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn stats_are_saved_and_loaded() {
+        let dir = test_dir("stats");
+        let backend = LocalBackend::new(&dir.to_string_lossy(), false);
+        assert_eq!(backend.load_stats().hits, 0);
+        let stats = Stats { hits: 3, misses: 4 };
+        backend.save_stats(&stats).unwrap();
+        let loaded = backend.load_stats();
+        assert_eq!((loaded.hits, loaded.misses), (3, 4));
+        assert!(backend.entries().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,148 @@
+//! This module translates the structured options of targets into the flags of a compiler.
+//!
+//! The flags are those of gcc and clang, which every supported compiler takes. MSVC style
+//! compilers like cl and clang-cl are rejected when the config is read.
+use crate::languages::Language;
+use crate::utils::{log, LogLevel};
+use std::path::Path;
+use toml::Value;
+
+/// The valid values of the warnings option, from the fewest warnings to the most
+pub const WARNING_LEVELS: [&str; 5] = ["none", "default", "all", "extra", "strict"];
+/// The valid values of the optimization option
+pub const OPTIMIZATION_LEVELS: [&str; 8] = ["0", "1", "2", "3", "s", "z", "fast", "debug"];
+
+/// Returns true if a compiler takes MSVC style flags, like cl and clang-cl
+/// These are not supported, since every command is written with gcc style flags
+/// # Arguments
+/// * `compiler` - The compiler, which may be a path and may be followed by arguments
+pub fn is_msvc(compiler: &str) -> bool {
+    let program = compiler.split_whitespace().next().unwrap_or_default();
+    let name = Path::new(program)
+        .file_stem()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    name == "cl" || name == "clang-cl"
+}
+
+/// Returns the flag adding an include dir
+/// # Arguments
+/// * `dir` - The include dir
+pub fn include_dir(dir: &str) -> String {
+    format!("-I{}", dir)
+}
+
+/// Returns the flag adding a define
+/// # Arguments
+/// * `define` - The define, either a name or name=value
+pub fn define(define: &str) -> String {
+    format!("-D{}", define)
+}
+
+/// Returns the flags enabling a level of warnings
+/// # Arguments
+/// * `level` - One of WARNING_LEVELS
+pub fn warnings(level: &str) -> &'static str {
+    match level {
+        "none" => "-w",
+        "all" => "-Wall",
+        "extra" => "-Wall -Wextra",
+        "strict" => "-Wall -Wextra -Wpedantic -Wshadow -Wconversion",
+        _ => "",
+    }
+}
+
+/// Returns the flag selecting a language standard
+/// # Arguments
+/// * `std` - The standard, like c11, gnu17 or c++20
+pub fn std(std: &str) -> String {
+    format!("-std={}", std)
+}
+
+/// Returns the flag selecting a level of optimization
+/// # Arguments
+/// * `level` - One of OPTIMIZATION_LEVELS
+pub fn optimization(level: &str) -> &'static str {
+    match level {
+        "0" => "-O0",
+        "1" => "-O1",
+        "2" => "-O2",
+        "3" => "-O3",
+        "s" => "-Os",
+        "z" => "-Oz",
+        "fast" => "-Ofast",
+        "debug" => "-Og",
+        _ => "",
+    }
+}
+
+/// Returns the argument linking a library
+/// Libraries given as a path are linked by that path
+/// # Arguments
+/// * `lib` - The name of the library, like m or pthread, or its path
+pub fn link_lib(lib: &str) -> String {
+    let is_path = lib.contains('/') || lib.contains('\\') || Path::new(lib).extension().is_some();
+    if is_path {
+        lib.to_string()
+    } else {
+        format!("-l{}", lib)
+    }
+}
+
+/// Returns the extension of the precompiled headers a compiler writes and finds
+/// gcc writes .gch files and clang .pch files, both next to the header they precompile
+/// # Arguments
+/// * `compiler` - The compiler, which may be a path and may be followed by arguments
+pub fn pch_extension(compiler: &str) -> &'static str {
+    let program = compiler.split_whitespace().next().unwrap_or_default();
+    let name = Path::new(program)
+        .file_name()
+        .and_then(|x| x.to_str())
+        .unwrap_or_default();
+    if name.contains("clang") {
+        "pch"
+    } else {
+        "gch"
+    }
+}
+
+/// Returns true if a language standard is one of c++, as opposed to one of c
+/// # Arguments
+/// * `std` - The standard, like c11, gnu17 or c++20
+pub fn is_cxx_std(std: &str) -> bool {
+    std.starts_with("c++") || std.starts_with("gnu++")
+}
+
+/// Returns true if a language standard is passed when compiling sources of a language
+/// c++ standards go to c++ sources and c standards to c and objective c sources
+/// # Arguments
+/// * `std` - The standard, like c11, gnu17 or c++20
+/// * `language` - The language of the sources
+pub fn std_applies(std: &str, language: &Language) -> bool {
+    if is_cxx_std(std) {
+        language.is_cxx()
+    } else {
+        language.name == "c" || language.name == "objc"
+    }
+}
+
+/// Parses a level of optimization from the config, which is a number or a string
+/// Exits if it is not one of OPTIMIZATION_LEVELS
+/// # Arguments
+/// * `value` - The value of the optimization key
+pub fn parse_optimization(value: &Value) -> String {
+    let level = match value {
+        Value::Integer(level) => level.to_string(),
+        Value::String(level) => level.clone(),
+        _ => String::new(),
+    };
+    if !OPTIMIZATION_LEVELS.contains(&level.as_str()) {
+        log(
+            LogLevel::Error,
+            &format!("optimization must be one of {}", OPTIMIZATION_LEVELS.join(", ")),
+        );
+        std::process::exit(1);
+    }
+    level
+}
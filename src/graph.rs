@@ -0,0 +1,202 @@
+//! This module contains the dependency graph of the targets of a project
+use crate::utils::{log, LogLevel, TargetConfig};
+
+/// The dependency graph of a set of targets
+/// Nodes are the targets, edges point from a target to the targets it depends on.
+/// Deps on names outside of the graph, like the targets of packages, are not part of it.
+#[derive(Debug, Clone)]
+pub struct TargetGraph {
+    names: Vec<String>,
+    deps: Vec<Vec<usize>>,
+    order: Vec<usize>,
+}
+
+impl TargetGraph {
+    /// Builds the graph of the given targets
+    /// Exits with an error if a dep is unknown or the deps form a cycle
+    /// # Arguments
+    /// * `targets` - The targets of the project
+    /// * `external` - Names outside of targets that deps may refer to
+    pub fn new(targets: &[TargetConfig], external: &[String]) -> TargetGraph {
+        let names: Vec<String> = targets.iter().map(|x| x.name.clone()).collect();
+        let mut deps = Vec::new();
+        let mut unknown = false;
+        for target in targets {
+            let mut target_deps = Vec::new();
+            for dep in &target.deps {
+                if let Some(index) = names.iter().position(|x| *x == dep.name) {
+                    target_deps.push(index);
+                } else if !external.contains(&dep.name) {
+                    log(
+                        LogLevel::Error,
+                        &format!("Target: {} depends on unknown target: {}", target.name, dep.name),
+                    );
+                    let candidates = names.iter().chain(external.iter());
+                    if let Some(suggestion) = TargetGraph::suggest(&dep.name, candidates) {
+                        log(LogLevel::Error, &format!("\tDid you mean: {}?", suggestion));
+                    }
+                    unknown = true;
+                }
+            }
+            deps.push(target_deps);
+        }
+        if unknown {
+            std::process::exit(1);
+        }
+
+        let mut graph = TargetGraph {
+            names,
+            deps,
+            order: Vec::new(),
+        };
+        graph.order = graph.sort().unwrap_or_else(|| {
+            let cycle = graph.find_cycle();
+            log(
+                LogLevel::Error,
+                &format!("Circular dependency found: {}", cycle.join(" -> ")),
+            );
+            std::process::exit(1);
+        });
+        graph
+    }
+
+    /// Returns the given targets sorted so that every target comes after its deps
+    /// # Arguments
+    /// * `targets` - The targets the graph was built from
+    pub fn arrange(&self, targets: &[TargetConfig]) -> Vec<TargetConfig> {
+        self.order
+            .iter()
+            .filter_map(|&x| targets.iter().find(|y| y.name == self.names[x]))
+            .cloned()
+            .collect()
+    }
+
+    //kahn's algorithm, ties are broken by the order the targets were declared in
+    //returns None if the graph contains a cycle
+    fn sort(&self) -> Option<Vec<usize>> {
+        let mut num_deps: Vec<usize> = self.deps.iter().map(|x| x.len()).collect();
+        let mut order = Vec::new();
+        while order.len() < self.names.len() {
+            let ready = (0..self.names.len()).find(|&x| num_deps[x] == 0)?;
+            num_deps[ready] = usize::MAX;
+            for (index, deps) in self.deps.iter().enumerate() {
+                for _ in deps.iter().filter(|&&x| x == ready) {
+                    num_deps[index] -= 1;
+                }
+            }
+            order.push(ready);
+        }
+        Some(order)
+    }
+
+    //returns the names along a cycle, starting and ending with the same target
+    fn find_cycle(&self) -> Vec<&str> {
+        //0 is unvisited, 1 is on the current path and 2 is done
+        let mut state = vec![0; self.names.len()];
+        let mut path = Vec::new();
+        for start in 0..self.names.len() {
+            if let Some(cycle) = self.visit(start, &mut state, &mut path) {
+                return cycle.iter().map(|&x| self.names[x].as_str()).collect();
+            }
+        }
+        Vec::new()
+    }
+
+    fn visit(&self, index: usize, state: &mut [u8], path: &mut Vec<usize>) -> Option<Vec<usize>> {
+        if state[index] == 2 {
+            return None;
+        }
+        if state[index] == 1 {
+            let start = path.iter().position(|&x| x == index).unwrap();
+            let mut cycle = path[start..].to_vec();
+            cycle.push(index);
+            return Some(cycle);
+        }
+        state[index] = 1;
+        path.push(index);
+        for &dep in &self.deps[index] {
+            if let Some(cycle) = self.visit(dep, state, path) {
+                return Some(cycle);
+            }
+        }
+        path.pop();
+        state[index] = 2;
+        None
+    }
+
+    //returns the closest candidate to an unknown name if it is close enough to be a typo
+    fn suggest<'b>(name: &str, candidates: impl Iterator<Item = &'b String>) -> Option<&'b String> {
+        candidates
+            .map(|x| (strsim::levenshtein(name, x), x))
+            .filter(|(distance, x)| *distance <= (x.len() / 3).max(1))
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, x)| x)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //builds a graph from the names of targets and the names of their deps
+    fn graph_of(targets: &[(&str, &[&str])]) -> TargetGraph {
+        let names: Vec<String> = targets.iter().map(|(name, _)| name.to_string()).collect();
+        let deps = targets
+            .iter()
+            .map(|(_, deps)| {
+                deps.iter()
+                    .map(|dep| names.iter().position(|x| x == dep).unwrap())
+                    .collect()
+            })
+            .collect();
+        TargetGraph {
+            names,
+            deps,
+            order: Vec::new(),
+        }
+    }
+
+    fn sorted_names(graph: &TargetGraph) -> Option<Vec<&str>> {
+        let order = graph.sort()?;
+        Some(order.iter().map(|&x| graph.names[x].as_str()).collect())
+    }
+
+    #[test]
+    fn targets_come_after_their_deps() {
+        let graph = graph_of(&[
+            ("main", &["libgame", "libengine"]),
+            ("libgame", &["libengine"]),
+            ("libengine", &[]),
+        ]);
+        assert_eq!(
+            sorted_names(&graph).unwrap(),
+            ["libengine", "libgame", "main"]
+        );
+    }
+
+    #[test]
+    fn ties_keep_the_declared_order() {
+        let graph = graph_of(&[("tests", &["lib"]), ("b", &[]), ("lib", &[]), ("a", &[])]);
+        assert_eq!(sorted_names(&graph).unwrap(), ["b", "lib", "tests", "a"]);
+    }
+
+    #[test]
+    fn cycles_are_found_with_their_path() {
+        let graph = graph_of(&[("main", &["a"]), ("a", &["b"]), ("b", &["a"])]);
+        assert_eq!(sorted_names(&graph), None);
+        assert_eq!(graph.find_cycle(), ["a", "b", "a"]);
+
+        let graph = graph_of(&[("a", &["a"])]);
+        assert_eq!(graph.find_cycle(), ["a", "a"]);
+    }
+
+    #[test]
+    fn unknown_deps_suggest_close_names() {
+        let names = ["libengine", "libgame", "main"].map(String::from);
+        let suggestion = TargetGraph::suggest("libengien", names.iter());
+        assert_eq!(suggestion.map(String::as_str), Some("libengine"));
+        let suggestion = TargetGraph::suggest("mainn", names.iter());
+        assert_eq!(suggestion.map(String::as_str), Some("main"));
+        assert_eq!(TargetGraph::suggest("network", names.iter()), None);
+    }
+}
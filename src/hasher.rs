@@ -1,11 +1,119 @@
 //! This module contains functions for hashing files and checking if they have changed.
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+//!
+//! The hashes of a target are kept in a store that is keyed by the outputs of the target,
+//! its objects and its binary. Every output records the hash of the command that produced it
+//! and the hashes of the files it was produced from.
+//!
+//! Alongside the hash, every file records its modification time, size and inode.
+//! A file whose metadata did not change is not read again, and no file is hashed
+//! more than once during a build.
+//!
+//! The store is saved as a versioned text file, like `.bld_cpp/debug/main.linux.hashes`,
+//! written to a temporary file first and renamed over the old one,
+//! so an interrupted build never leaves a half written store behind.
+//! ```text
+//! builder_cpp hashes 4 sha1
+//! output .bld_cpp/debug/obj_linux/main/src/main.cpp.o 5b6ef6f9...
+//! file ./src/main.cpp 0a4d55a8... 1697040000123456789 1024 2883617
+//! file ./src/include/my\sheader.h 9c1185a5... 1697040000123456789 311 2883620
+//! checksum 3f786850...
+//! ```
+//! The header names the hash algorithm, and a store written with another algorithm is discarded,
+//! like earlier versions, which do not name it. Paths are escaped so they can contain any
+//! character, and the checksum covers every line before it.
+//! A store that fails to parse is discarded, which rebuilds the whole target.
+use std::fs::File;
+use std::io::Read;
 use std::path::Path;
 use crate::utils::{log, LogLevel};
 use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
+use std::time::UNIX_EPOCH;
 use sha1::{Sha1, Digest};
 
+//followed by the name of the hash algorithm
+static HEADER: &str = "builder_cpp hashes 4";
+
+static ALGORITHM: OnceLock<HashAlgorithm> = OnceLock::new();
+//the files hashed during this build, by path along with the metadata they had
+//the hash is filled in by the first thread that needs it
+type HashedFiles = HashMap<String, (FileMeta, Arc<OnceLock<String>>)>;
+static HASHED: LazyLock<Mutex<HashedFiles>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// The algorithm used to hash the contents of files
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum HashAlgorithm {
+    #[default]
+    Sha1,
+    Blake3,
+    Xxh3,
+}
+
+impl HashAlgorithm {
+    /// Parses the name of an algorithm, which is one of sha1, blake3 or xxh3
+    /// # Arguments
+    /// * `name` - The name of the algorithm
+    pub fn parse(name: &str) -> Option<HashAlgorithm> {
+        match name {
+            "sha1" => Some(HashAlgorithm::Sha1),
+            "blake3" => Some(HashAlgorithm::Blake3),
+            "xxh3" => Some(HashAlgorithm::Xxh3),
+            _ => None,
+        }
+    }
+
+    /// Returns the name of the algorithm, as it is parsed
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgorithm::Sha1 => "sha1",
+            HashAlgorithm::Blake3 => "blake3",
+            HashAlgorithm::Xxh3 => "xxh3",
+        }
+    }
+}
+
+/// The recorded state of a file
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FileHash {
+    /// The hash of the contents of the file
+    pub hash: String,
+    /// The modification time in nanoseconds since the unix epoch
+    pub mtime: u64,
+    /// The size in bytes
+    pub size: u64,
+    /// The inode, always 0 on windows
+    pub inode: u64,
+}
+
+/// The hashes recorded for a single output of a target
+#[derive(Debug, Clone, Default)]
+pub struct OutputHashes {
+    /// The hash of the command that produced the output
+    pub cmd: Option<String>,
+    /// The recorded state of the files the output was produced from by path
+    pub files: HashMap<String, FileHash>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FileMeta {
+    mtime: u64,
+    size: u64,
+    inode: u64,
+}
+
+impl FileHash {
+    fn meta(&self) -> FileMeta {
+        FileMeta {
+            mtime: self.mtime,
+            size: self.size,
+            inode: self.inode,
+        }
+    }
+}
+
+/// The hashes of a target by output path
+pub type HashStore = HashMap<String, OutputHashes>;
+
 // Hashes a file and returns the hash as a string.
 fn hash_file(path: &str) -> String {
     let mut file = File::open(path).unwrap();
@@ -16,8 +124,8 @@ fn hash_file(path: &str) -> String {
         log(LogLevel::Error, &format!("Error: {}", why));
         std::process::exit(1);
     }).len();
-    let mut buffer = [0; CHUNK_SIZE];
-    let mut hasher = Sha1::new();
+    let mut buffer = vec![0; CHUNK_SIZE];
+    let mut hasher = ContentHasher::new();
 
     while limit > 0 {
         let read_size = if limit < CHUNK_SIZE as u64 {
@@ -32,95 +140,483 @@ fn hash_file(path: &str) -> String {
         limit -= read as u64;
         hasher.update(&buffer[0..read]);
     }
-    let result = hasher.finalize();
+    hasher.finalize()
+}
+
+// Hashes a string and returns the hash as a string.
+fn hash_str(contents: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(contents.as_bytes());
     let mut hash = String::new();
-    for byte in result {
+    for byte in hasher.finalize() {
         hash.push_str(&format!("{:02x}", byte));
     }
-    return hash;
+    hash
 }
 
-/// Returns the hash of a file if it exists in the path_hash.
+//the state of a hash of the configured algorithm
+enum ContentHasher {
+    Sha1(Sha1),
+    Blake3(Box<blake3::Hasher>),
+    Xxh3(Box<xxhash_rust::xxh3::Xxh3>),
+}
+
+impl ContentHasher {
+    fn new() -> ContentHasher {
+        match algorithm() {
+            HashAlgorithm::Sha1 => ContentHasher::Sha1(Sha1::new()),
+            HashAlgorithm::Blake3 => ContentHasher::Blake3(Box::new(blake3::Hasher::new())),
+            HashAlgorithm::Xxh3 => ContentHasher::Xxh3(Box::default()),
+        }
+    }
+
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            ContentHasher::Sha1(hasher) => hasher.update(data),
+            ContentHasher::Blake3(hasher) => {
+                hasher.update(data);
+            }
+            ContentHasher::Xxh3(hasher) => hasher.update(data),
+        }
+    }
+
+    fn finalize(self) -> String {
+        match self {
+            ContentHasher::Sha1(hasher) => {
+                let mut hash = String::new();
+                for byte in hasher.finalize() {
+                    hash.push_str(&format!("{:02x}", byte));
+                }
+                hash
+            }
+            ContentHasher::Blake3(hasher) => hasher.finalize().to_hex().to_string(),
+            ContentHasher::Xxh3(hasher) => format!("{:032x}", hasher.digest128()),
+        }
+    }
+}
+
+//returns the metadata of a file that tells if it may have changed, None if it does not exist
+fn file_meta(path: &str) -> Option<FileMeta> {
+    let metadata = std::fs::metadata(path).ok()?;
+    let mtime = metadata
+        .modified()
+        .ok()
+        .and_then(|x| x.duration_since(UNIX_EPOCH).ok())
+        .map(|x| x.as_nanos() as u64)
+        .unwrap_or(0);
+    #[cfg(target_os = "windows")]
+    let inode = 0;
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let inode = std::os::unix::fs::MetadataExt::ino(&metadata);
+    Some(FileMeta {
+        mtime,
+        size: metadata.len(),
+        inode,
+    })
+}
+
+//returns the hash of a file with the given metadata, hashing it only once per build
+//a file that is written during the build, like a static lib, gets new metadata and is hashed again
+fn current_hash(path: &str, meta: FileMeta) -> String {
+    let hash = {
+        let mut hashed = HASHED.lock().unwrap();
+        match hashed.get(path) {
+            Some((hashed_meta, hash)) if *hashed_meta == meta => Arc::clone(hash),
+            _ => {
+                let hash = Arc::new(OnceLock::new());
+                hashed.insert(path.to_string(), (meta, Arc::clone(&hash)));
+                hash
+            }
+        }
+    };
+    hash.get_or_init(|| hash_file(path)).clone()
+}
+
+/// Sets the algorithm used to hash the contents of files
+/// Has to be called before any file is hashed, sha1 is used otherwise
+/// # Arguments
+/// * `algorithm` - The hash algorithm
+pub fn set_algorithm(algorithm: HashAlgorithm) {
+    if ALGORITHM.set(algorithm).is_err() {
+        log(LogLevel::Debug, "Hash algorithm already set");
+    }
+}
+
+//returns the hash algorithm of this build
+fn algorithm() -> HashAlgorithm {
+    ALGORITHM.get().copied().unwrap_or_default()
+}
+
+//returns the header of a store written with an algorithm
+fn header(algorithm: HashAlgorithm) -> String {
+    format!("{} {}", HEADER, algorithm.name())
+}
+
+/// Returns the state of a file recorded for an output if there is one.
 /// Otherwise returns None.
 /// # Arguments
+/// * `output` - The output the file was used for.
 /// * `path` - The path of the file to get the hash of.
-/// * `path_hash` - The hashmap of paths and hashes.
-pub fn get_hash(path: &str, path_hash: &HashMap<String, String>) -> Option<String> {
-    if path_hash.contains_key(path) {
-        return Some(path_hash.get(path).unwrap().to_string());
-    }
-    return None;
+/// * `store` - The hash store.
+pub fn get_hash<'s>(output: &str, path: &str, store: &'s HashStore) -> Option<&'s FileHash> {
+    store.get(output)?.files.get(path)
 }
 
-/// Loads the hashes from a file and returns them as a hashmap.
+/// Loads the hash store from a file.
+/// Returns an empty store if the file does not exist, is corrupted or was written with another
+/// hash algorithm.
 /// # Arguments
 /// * `path` - The path of the file to load the hashes from.
-pub fn load_hashes_from_file(path: &str) -> HashMap<String, String> {
-    let mut path_hash: HashMap<String, String> = HashMap::new();
-    let path = Path::new(path);
-    if !path.exists() {
-        return path_hash;
-    }
-    let mut file = OpenOptions::new().read(true).open(path).unwrap();
-    let mut contents = String::new();
-    file.read_to_string(&mut contents).unwrap();
-    for line in contents.lines() {
-        if line.is_empty() {
-            continue;
-        }
-        let mut split = line.split(" ");
-        let path = split.next().unwrap();
-        let hash = split.next().unwrap();
-        path_hash.insert(path.to_string(), hash.to_string());
+pub fn load_hashes_from_file(path: &str) -> HashStore {
+    if !Path::new(path).exists() {
+        return HashStore::new();
     }
-    return path_hash;
+    let contents = std::fs::read_to_string(path).unwrap_or_default();
+    let header = header(algorithm());
+    if contents.starts_with(HEADER) && contents.lines().next() != Some(header.as_str()) {
+        log(
+            LogLevel::Info,
+            &format!(
+                "Hash file: {} was written with another hash algorithm, rebuilding everything it covers",
+                path
+            ),
+        );
+        return HashStore::new();
+    }
+    parse_store(&contents, algorithm()).unwrap_or_else(|| {
+        log(
+            LogLevel::Warn,
+            &format!("Hash file: {} is corrupted, rebuilding everything it covers", path),
+        );
+        HashStore::new()
+    })
 }
 
-/// Saves the hashes to a file.
+/// Saves the hash store to a file.
+/// The store is written to a temporary file that then replaces the old one.
 /// # Arguments
 /// * `path` - The path of the file to save the hashes to.
-/// * `path_hash` - The hashmap of paths and hashes.
-pub fn save_hashes_to_file(path: &str, path_hash: &HashMap<String, String>) {
-    let mut file = OpenOptions::new().write(true).create(true).open(path).unwrap_or_else(|_| {
-        log(LogLevel::Error, &format!("Failed to open file: {}", path));
+/// * `store` - The hash store.
+pub fn save_hashes_to_file(path: &str, store: &HashStore) {
+    let contents = store_contents(store, algorithm());
+    let tmp_path = format!("{}.tmp", path);
+    std::fs::write(&tmp_path, contents).unwrap_or_else(|why| {
+        log(LogLevel::Error, &format!("Failed to write file: {}", tmp_path));
+        log(LogLevel::Error, &format!("Error: {}", why));
+        std::process::exit(1);
+    });
+    std::fs::rename(&tmp_path, path).unwrap_or_else(|why| {
+        log(LogLevel::Error, &format!("Failed to replace file: {}", path));
+        log(LogLevel::Error, &format!("Error: {}", why));
         std::process::exit(1);
     });
-    for (path, hash) in path_hash {
-        let line = format!("{} {}\n", path, hash);
-        file.write(line.as_bytes()).unwrap();
+}
+
+//returns the contents of the file a store is saved to
+fn store_contents(store: &HashStore, algorithm: HashAlgorithm) -> String {
+    let mut contents = header(algorithm);
+    contents.push('\n');
+
+    //sorted so that the same store is always written the same way
+    let mut outputs: Vec<&String> = store.keys().collect();
+    outputs.sort();
+    for output in outputs {
+        let hashes = &store[output];
+        let cmd = hashes.cmd.as_deref().unwrap_or("-");
+        contents.push_str(&format!("output {} {}\n", escape(output), cmd));
+        let mut files: Vec<&String> = hashes.files.keys().collect();
+        files.sort();
+        for file in files {
+            let file_hash = &hashes.files[file];
+            contents.push_str(&format!(
+                "file {} {} {} {} {}\n",
+                escape(file),
+                file_hash.hash,
+                file_hash.mtime,
+                file_hash.size,
+                file_hash.inode
+            ));
+        }
     }
+    let checksum = hash_str(&contents);
+    contents.push_str(&format!("checksum {}\n", checksum));
+    contents
 }
 
-/// Checks if a file has changed.
+/// Checks if a file has changed since it was last saved for an output.
 /// # Arguments
+/// * `output` - The output the file is used for.
 /// * `path` - The path of the file to check.
-/// * `path_hash` - The hashmap of paths and hashes.
-pub fn is_file_changed(path: &str, path_hash: &HashMap<String, String>) -> bool {
-    let hash = get_hash(path, path_hash);
-    if hash.is_none() {
-        return true;
+/// * `store` - The hash store.
+pub fn is_file_changed(output: &str, path: &str, store: &HashStore) -> bool {
+    let recorded = match get_hash(output, path, store) {
+        Some(recorded) => recorded,
+        None => return true,
+    };
+    let meta = match file_meta(path) {
+        Some(meta) => meta,
+        None => return true,
+    };
+    if recorded.meta() == meta {
+        return false;
     }
-    let hash = hash.unwrap();
-    let new_hash = hash_file(path);
-    let result = hash != new_hash;
-    result
+    current_hash(path, meta) != recorded.hash
 }
 
-/// Saves the hash of a file to the hashmap.
+/// Saves the hash of a file used for an output to the store.
 /// # Arguments
+/// * `output` - The output the file is used for.
 /// * `path` - The path of the file to save the hash of.
-/// * `path_hash` - The hashmap of paths and hashes.
-pub fn save_hash(path: &str, path_hash: &mut HashMap<String, String>) {
-    let new_hash = hash_file(path);
-    let hash = get_hash(path, path_hash);
-    if hash.is_none() {
-        path_hash.insert(path.to_string(), new_hash);
-        return;
-    }
-    let hash = hash.unwrap();
-    if hash != new_hash {
+/// * `store` - The hash store.
+pub fn save_hash(output: &str, path: &str, store: &mut HashStore) {
+    let meta = file_meta(path).unwrap_or_else(|| {
+        log(LogLevel::Error, &format!("Failed to get metadata for file: {}", path));
+        std::process::exit(1);
+    });
+    let new_hash = FileHash {
+        hash: current_hash(path, meta),
+        mtime: meta.mtime,
+        size: meta.size,
+        inode: meta.inode,
+    };
+    let files = &mut store.entry(output.to_string()).or_default().files;
+    if files.get(path).is_some_and(|hash| hash.hash != new_hash.hash) {
         log(LogLevel::Info, &format!("File changed, updating hash for file: {}", path));
-        path_hash.insert(path.to_string(), new_hash);
-        return;
+    }
+    files.insert(path.to_string(), new_hash);
+}
+
+/// Updates the recorded metadata of the files of an output that were hashed during this build
+/// and turned out unchanged, so they are not hashed again by the next build.
+/// Returns true if anything was updated.
+/// # Arguments
+/// * `output` - The output the files are used for.
+/// * `store` - The hash store.
+pub fn refresh_hashes(output: &str, store: &mut HashStore) -> bool {
+    let hashes = match store.get_mut(output) {
+        Some(hashes) => hashes,
+        None => return false,
+    };
+    let hashed = HASHED.lock().unwrap();
+    let mut refreshed = false;
+    for (path, file_hash) in hashes.files.iter_mut() {
+        let (meta, hash) = match hashed.get(path) {
+            Some(hashed) => hashed,
+            None => continue,
+        };
+        if *meta != file_hash.meta() && hash.get() == Some(&file_hash.hash) {
+            file_hash.mtime = meta.mtime;
+            file_hash.size = meta.size;
+            file_hash.inode = meta.inode;
+            refreshed = true;
+        }
+    }
+    refreshed
+}
+
+/// Checks if the command that produces an output has changed since it was last saved.
+/// # Arguments
+/// * `output` - The path of the file the command produces.
+/// * `cmd` - The fully expanded command.
+/// * `store` - The hash store.
+pub fn is_cmd_changed(output: &str, cmd: &str, store: &HashStore) -> bool {
+    store.get(output).and_then(|x| x.cmd.as_ref()) != Some(&hash_str(cmd))
+}
+
+/// Saves the hash of the command that produces an output to the store.
+/// # Arguments
+/// * `output` - The path of the file the command produces.
+/// * `cmd` - The fully expanded command.
+/// * `store` - The hash store.
+pub fn save_cmd_hash(output: &str, cmd: &str, store: &mut HashStore) {
+    store.entry(output.to_string()).or_default().cmd = Some(hash_str(cmd));
+}
+
+/// Forgets everything recorded for an output, before it is produced again.
+/// # Arguments
+/// * `output` - The path of the output.
+/// * `store` - The hash store.
+pub fn clear_hashes(output: &str, store: &mut HashStore) {
+    store.remove(output);
+}
+
+//parses the contents of a store file, returns None if they are corrupted
+//or were written with another algorithm
+fn parse_store(contents: &str, algorithm: HashAlgorithm) -> Option<HashStore> {
+    let (body, checksum_line) = contents.trim_end_matches('\n').rsplit_once('\n')?;
+    let body = format!("{}\n", body);
+    if checksum_line.strip_prefix("checksum ")? != hash_str(&body) {
+        return None;
+    }
+    let mut lines = body.lines();
+    if lines.next()? != header(algorithm) {
+        return None;
+    }
+    let mut store = HashStore::new();
+    let mut current: Option<&mut OutputHashes> = None;
+    for line in lines {
+        let fields: Vec<&str> = line.split(' ').collect();
+        let path = unescape(fields.get(1)?)?;
+        match (fields[0], fields.len()) {
+            ("output", 3) => {
+                let cmd = if fields[2] == "-" { None } else { Some(fields[2].to_string()) };
+                let hashes = store.entry(path).or_default();
+                hashes.cmd = cmd;
+                current = Some(hashes);
+            }
+            ("file", 6) => {
+                let file_hash = FileHash {
+                    hash: fields[2].to_string(),
+                    mtime: fields[3].parse().ok()?,
+                    size: fields[4].parse().ok()?,
+                    inode: fields[5].parse().ok()?,
+                };
+                current.as_mut()?.files.insert(path, file_hash);
+            }
+            _ => return None,
+        }
+    }
+    Some(store)
+}
+
+//escapes a path so that it contains no spaces or line breaks
+fn escape(path: &str) -> String {
+    let mut escaped = String::new();
+    for c in path.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ' ' => escaped.push_str("\\s"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+//reverses escape, returns None for an invalid escape sequence
+fn unescape(escaped: &str) -> Option<String> {
+    let mut path = String::new();
+    let mut chars = escaped.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            path.push(c);
+            continue;
+        }
+        match chars.next()? {
+            '\\' => path.push('\\'),
+            's' => path.push(' '),
+            'n' => path.push('\n'),
+            'r' => path.push('\r'),
+            't' => path.push('\t'),
+            _ => return None,
+        }
+    }
+    Some(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("bld_cpp_{}_{}", name, std::process::id()))
+            .to_string_lossy()
+            .to_string()
+    }
+
+    fn store_of(paths: &[&str]) -> HashStore {
+        let mut store = HashStore::new();
+        for (i, path) in paths.iter().enumerate() {
+            let output = store.entry(format!("{}.o", path)).or_default();
+            output.cmd = Some(format!("cmd{}", i));
+            let file_hash = FileHash {
+                hash: format!("hash{}", i),
+                mtime: 1697040000123456789,
+                size: i as u64,
+                inode: 2883617,
+            };
+            output.files.insert(path.to_string(), file_hash);
+        }
+        store
+    }
+
+    #[test]
+    fn paths_are_escaped_and_unescaped() {
+        for path in [
+            "./src/my file.c",
+            "./src/new\nline\n.c",
+            "C:\\src\\main.c",
+            "\\s\t\r",
+        ] {
+            let escaped = escape(path);
+            assert!(!escaped.contains([' ', '\n', '\r', '\t']));
+            assert_eq!(unescape(&escaped).as_deref(), Some(path));
+        }
+        assert_eq!(unescape("trailing\\"), None);
+        assert_eq!(unescape("\\x"), None);
+    }
+
+    #[test]
+    fn stores_are_saved_and_loaded() {
+        let path = temp_path("round_trip.hashes");
+        let paths = ["./src/my file.c", "./src/new\nline.c", "C:\\src\\main.c"];
+        let store = store_of(&paths);
+        save_hashes_to_file(&path, &store);
+        let loaded = load_hashes_from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.len(), paths.len());
+        for (output, hashes) in &store {
+            assert_eq!(loaded[output].cmd, hashes.cmd);
+            assert_eq!(loaded[output].files, hashes.files);
+        }
+    }
+
+    #[test]
+    fn damaged_stores_are_discarded() {
+        let store = store_of(&["./src/main.c", "./src/util.c"]);
+        let contents = store_contents(&store, HashAlgorithm::Sha1);
+        assert!(parse_store(&contents, HashAlgorithm::Sha1).is_some());
+
+        let bad_checksum = contents.replace("hash1", "hash2");
+        let truncated = &contents[..contents.len() / 2];
+        //a valid checksum, so only the header is wrong
+        let body = contents.split("checksum ").next().unwrap();
+        let body = body.replacen(HEADER, "builder_cpp hashes 9", 1);
+        let unknown_version = format!("{}checksum {}\n", body, hash_str(&body));
+        for damaged in [
+            bad_checksum.as_str(),
+            truncated,
+            &unknown_version,
+            "",
+            "\n\n",
+        ] {
+            assert!(parse_store(damaged, HashAlgorithm::Sha1).is_none());
+        }
+
+        let path = temp_path("damaged.hashes");
+        std::fs::write(&path, truncated).unwrap();
+        assert!(load_hashes_from_file(&path).is_empty());
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn stores_of_other_algorithms_are_discarded() {
+        let store = store_of(&["./src/main.c"]);
+        let contents = store_contents(&store, HashAlgorithm::Blake3);
+        assert!(contents.starts_with("builder_cpp hashes 4 blake3\n"));
+        assert!(parse_store(&contents, HashAlgorithm::Blake3).is_some());
+        assert!(parse_store(&contents, HashAlgorithm::Sha1).is_none());
+        assert!(parse_store(&contents, HashAlgorithm::Xxh3).is_none());
+        for algorithm in [
+            HashAlgorithm::Sha1,
+            HashAlgorithm::Blake3,
+            HashAlgorithm::Xxh3,
+        ] {
+            assert_eq!(HashAlgorithm::parse(algorithm.name()), Some(algorithm));
+        }
     }
 }
@@ -0,0 +1,108 @@
+//! This module limits how many compiler and linker processes run at once.
+//! The limit is shared by all targets and packages that are built in parallel.
+use crate::utils::{log, LogLevel};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How many jobs may run at once
+#[derive(Debug, Clone, PartialEq)]
+pub enum Jobs {
+    /// At most this many jobs
+    Count(usize),
+    /// One job per cpu, and no new jobs while the load average is above the number of cpus
+    Auto,
+}
+
+impl Jobs {
+    /// Parses the value of -j or the jobs key in the config
+    /// Valid values are a positive number or auto
+    /// # Arguments
+    /// * `value` - The value to parse
+    pub fn parse(value: &str) -> Option<Jobs> {
+        if value == "auto" {
+            return Some(Jobs::Auto);
+        }
+        match value.parse::<usize>() {
+            Ok(count) if count > 0 => Some(Jobs::Count(count)),
+            _ => None,
+        }
+    }
+}
+
+struct Limit {
+    running: usize,
+    max: usize,
+    check_load: bool,
+}
+
+static LIMIT: Mutex<Limit> = Mutex::new(Limit {
+    running: 0,
+    max: usize::MAX,
+    check_load: false,
+});
+static JOB_DONE: Condvar = Condvar::new();
+
+/// A running job, the slot is given back when it is dropped
+pub struct JobToken {
+    _private: (),
+}
+
+impl Drop for JobToken {
+    fn drop(&mut self) {
+        LIMIT.lock().unwrap().running -= 1;
+        JOB_DONE.notify_one();
+    }
+}
+
+/// Sets the number of jobs that may run at once
+/// Also sizes the thread pool so that the limit can actually be reached
+/// # Arguments
+/// * `jobs` - The job limit
+pub fn set_jobs(jobs: &Jobs) {
+    let cpus = std::thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+    let (max, check_load) = match jobs {
+        Jobs::Count(count) => (*count, false),
+        Jobs::Auto => (cpus, true),
+    };
+    let mut limit = LIMIT.lock().unwrap();
+    limit.max = max;
+    limit.check_load = check_load;
+    log(LogLevel::Info, &format!("Running at most {} jobs at once", max));
+    //the targets and sources waiting for a job occupy threads too
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(max.max(cpus))
+        .build_global()
+        .unwrap_or_else(|why| {
+            log(LogLevel::Debug, &format!("Thread pool already set up: {}", why));
+        });
+}
+
+/// Waits until another job may run and returns its token
+pub fn acquire() -> JobToken {
+    let cpus = std::thread::available_parallelism()
+        .map(|x| x.get())
+        .unwrap_or(1);
+    let mut limit = LIMIT.lock().unwrap();
+    loop {
+        let overloaded = limit.check_load
+            && limit.running > 0
+            && load_average().is_some_and(|load| load >= cpus as f64);
+        if limit.running < limit.max && !overloaded {
+            limit.running += 1;
+            return JobToken { _private: () };
+        }
+        //the load average changes without any job finishing, so it has to be polled
+        limit = JOB_DONE
+            .wait_timeout(limit, Duration::from_millis(250))
+            .unwrap()
+            .0;
+    }
+}
+
+//returns the load average of the last minute, if the os reports it
+fn load_average() -> Option<f64> {
+    let loadavg = std::fs::read_to_string("/proc/loadavg").ok()?;
+    loadavg.split_whitespace().next()?.parse().ok()
+}
@@ -0,0 +1,172 @@
+//! This module maps the extensions of source files to the languages they are written in.
+//!
+//! Every language has its own compiler and flags. The builtin languages are
+//! c, cxx, objc, objcxx, as (assembly run through the compiler driver) and nasm.
+//! They can be changed, and new ones added, with `[languages.<name>]` tables in the config.
+use crate::toolchain;
+use crate::utils::{self, log, LogLevel};
+use std::path::Path;
+use toml::{Table, Value};
+
+/// A language sources can be written in
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub name: String,
+    /// The compiler of the language, the compiler of the build config is used if there is none
+    pub compiler: Option<String>,
+    /// Flags passed to the compiler for every source of the language
+    pub flags: String,
+    /// The extensions of the sources of the language, without the leading dot
+    pub extensions: Vec<String>,
+}
+
+impl Language {
+    /// Returns the languages known without any config
+    pub fn builtin() -> Vec<Language> {
+        //objects are written in the format of the platform built for
+        let nasm_format = match toolchain::toolchain().os.as_str() {
+            "windows" => "-f win64",
+            "macos" => "-f macho64",
+            _ => "-f elf64",
+        };
+
+        let language = |name: &str, compiler: Option<&str>, flags: &str, extensions: &[&str]| {
+            Language {
+                name: name.to_string(),
+                compiler: compiler.map(|x| x.to_string()),
+                flags: flags.to_string(),
+                extensions: extensions.iter().map(|x| x.to_string()).collect(),
+            }
+        };
+        vec![
+            language("c", None, "", &["c"]),
+            language("cxx", None, "", &["cpp", "cc", "cxx", "c++"]),
+            language("objc", None, "", &["m"]),
+            language("objcxx", None, "", &["mm"]),
+            language("as", None, "", &["S", "s"]),
+            language("nasm", Some("nasm"), nasm_format, &["asm"]),
+        ]
+    }
+
+    /// Parses the languages table of a config on top of the builtin languages
+    /// Extensions given to a language are taken away from all others
+    /// # Arguments
+    /// * `config` - The whole config file
+    pub fn parse_languages(config: &Table) -> Vec<Language> {
+        let mut languages = Language::builtin();
+        let languages_toml = match config.get("languages") {
+            Some(Value::Table(languages_toml)) => languages_toml,
+            Some(_) => {
+                log(LogLevel::Error, "languages is not a table");
+                std::process::exit(1);
+            }
+            None => return languages,
+        };
+        for (name, language_toml) in languages_toml {
+            let get_str = |key: &str| {
+                language_toml.get(key).map(|x| {
+                    x.as_str()
+                        .unwrap_or_else(|| {
+                            log(
+                                LogLevel::Error,
+                                &format!("{} of language {} is not a string", key, name),
+                            );
+                            std::process::exit(1);
+                        })
+                        .to_string()
+                })
+            };
+            let compiler = get_str("compiler");
+            if let Some(compiler) = &compiler {
+                utils::check_compiler(compiler, &format!("languages.{}.compiler", name));
+            }
+            let flags = get_str("flags");
+            let extensions = language_toml.get("extensions").map(|x| {
+                x.as_array()
+                    .unwrap_or_else(|| {
+                        log(
+                            LogLevel::Error,
+                            &format!("extensions of language {} is not an array", name),
+                        );
+                        std::process::exit(1);
+                    })
+                    .iter()
+                    .map(|x| {
+                        x.as_str()
+                            .unwrap_or_else(|| {
+                                log(
+                                    LogLevel::Error,
+                                    &format!("extensions of language {} are strings", name),
+                                );
+                                std::process::exit(1);
+                            })
+                            .trim_start_matches('.')
+                            .to_string()
+                    })
+                    .collect::<Vec<String>>()
+            });
+
+            if let Some(extensions) = &extensions {
+                for language in &mut languages {
+                    language.extensions.retain(|x| !extensions.contains(x));
+                }
+            }
+            match languages.iter_mut().find(|x| x.name == *name) {
+                Some(language) => {
+                    if compiler.is_some() {
+                        language.compiler = compiler;
+                    }
+                    if let Some(flags) = flags {
+                        language.flags = flags;
+                    }
+                    if let Some(extensions) = extensions {
+                        language.extensions = extensions;
+                    }
+                }
+                None => {
+                    let extensions = extensions.unwrap_or_else(|| {
+                        log(
+                            LogLevel::Error,
+                            &format!("Could not find extensions of language {}", name),
+                        );
+                        std::process::exit(1);
+                    });
+                    languages.push(Language {
+                        name: name.clone(),
+                        compiler,
+                        flags: flags.unwrap_or_default(),
+                        extensions,
+                    });
+                }
+            }
+        }
+        languages
+    }
+
+    /// Returns the language a source file is written in, if it is a source file at all
+    /// # Arguments
+    /// * `languages` - The known languages
+    /// * `path` - The path of the file
+    pub fn of_path<'l>(languages: &'l [Language], path: &str) -> Option<&'l Language> {
+        let extension = Path::new(path).extension()?.to_str()?;
+        languages
+            .iter()
+            .find(|x| x.extensions.iter().any(|y| y == extension))
+    }
+
+    /// Returns true if sources of the language are assembled by nasm,
+    /// which takes different arguments than the compiler drivers
+    pub fn is_nasm(&self) -> bool {
+        self.name == "nasm"
+    }
+
+    /// Returns true if the language is compiled by the c++ compiler and gets the cxxflags
+    pub fn is_cxx(&self) -> bool {
+        self.name == "cxx" || self.name == "objcxx"
+    }
+
+    /// Returns true if the language is compiled by the c compiler
+    pub fn is_c(&self) -> bool {
+        self.name == "c" || self.name == "objc" || self.name == "as"
+    }
+}
@@ -3,21 +3,21 @@
 //! This library automatically configures various targets in your project
 //! and gives an easy interface to grab packages from github.
 //!
-//! The library uses config_linux.toml or config_win32.toml file to configure the project.
+//! The library uses a builder_cpp.toml file to configure the project, or the older config_linux.toml or config_win32.toml.
 //!
 //! # Installation
 //! To install this library, you need to have rust installed on your system.
-//! ```no_run
+//! ```console
 //! cargo install builder_cpp
 //! ```
 //!
 //! # Examples
 //! To get the various flags that can be passed to builder_cpp
-//! ```no_run
+//! ```console
 //! builder_cpp -h
 //! ```
 //! or
-//! ```no_run
+//! ```console
 //! builder_cpp --help
 //! ```
 //!
@@ -25,7 +25,7 @@
 //! Optional keys in toml are packages in build and deps in targets
 //! Project contains an executable and a library from a github repo
 //! ```toml
-//! # config_linux.toml
+//! # builder_cpp.toml
 //![build]
 //!compiler = "g++"
 //!packages = ["Dr-42/Nomu_Engine, master"]
@@ -73,3 +73,21 @@ pub mod builder;
 pub mod utils;
 /// Contains hashing related functions
 pub mod hasher;
+/// Contains the dependency graph of targets
+pub mod graph;
+/// Contains the limit on parallel compiler and linker jobs
+pub mod jobs;
+/// Contains the languages of source files
+pub mod languages;
+/// Contains the build profiles and the directories of their outputs
+pub mod profiles;
+/// Contains the translation of target options to compiler flags
+pub mod flags;
+/// Contains the libraries targets take from the system with pkg-config
+pub mod system_deps;
+/// Contains the cache of compiled objects shared by all projects
+pub mod cache;
+/// Contains the toolchain binaries are built with and the platform they are built for
+pub mod toolchain;
+/// Contains the conditions selecting parts of the config by platform
+pub mod platform;
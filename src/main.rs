@@ -1,4 +1,4 @@
-use builder_cpp::{bin_flags, utils::{self, log}};
+use builder_cpp::{bin_flags, builder, cache, hasher, jobs::{self, Jobs}, profiles, toolchain, utils::{self}};
 use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug)]
@@ -13,10 +13,25 @@ struct Args {
     /// Run the executable
     #[arg(short, long)]
     run: bool,
+    /// Number of compiler and linker jobs to run at once, or auto
+    #[arg(short, long)]
+    jobs: Option<String>,
+    /// Profile to build with, debug by default
+    #[arg(long, conflicts_with = "release")]
+    profile: Option<String>,
+    /// Build with the release profile
+    #[arg(long)]
+    release: bool,
+    /// Cross compile for a target triple with the toolchain in ./toolchains/<triple>.toml
+    #[arg(long)]
+    target: Option<String>,
+    /// Run compilers without the launcher of the config or of BUILDER_CPP_LAUNCHER
+    #[arg(long)]
+    no_launcher: bool,
 
-    /// Initialize a new project. See `init --help` for more info
+    /// Initialize a new project or manage the object cache. See `<command> --help` for more info
     #[command(subcommand)]
-    init: Option<Commands>,
+    command: Option<Commands>,
 
     /// Arguments to pass to the executable when running
     #[arg(long, num_args(1..))]
@@ -53,13 +68,26 @@ enum Commands {
         /// Initialize a C++ project
         cpp: bool,
     },
+    /// Show or clear the cache of compiled objects
+    Cache {
+        #[command(subcommand)]
+        action: CacheAction,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum CacheAction {
+    /// Show where the cache is, its size and how often it was hit
+    Stats,
+    /// Remove every object from the cache
+    Clear,
 }
 
 fn main() {
     let args = Args::parse();
 
-    if args.init.is_some() {
-        match args.init {
+    if args.command.is_some() {
+        match args.command {
             Some(Commands::Init { name, c, cpp }) => {
                 if c && cpp {
                     utils::log(
@@ -84,8 +112,15 @@ fn main() {
                     bin_flags::init_project(name, false);
                 }
             }
+            Some(Commands::Cache { action }) => {
+                match action {
+                    CacheAction::Stats => bin_flags::cache_stats(),
+                    CacheAction::Clear => bin_flags::clear_cache(),
+                }
+                std::process::exit(0);
+            }
             None => {
-                utils::log(utils::LogLevel::Error, "No init command specified");
+                utils::log(utils::LogLevel::Error, "No command specified");
                 std::process::exit(1);
             }
         }
@@ -103,7 +138,55 @@ fn main() {
         bin_flags::pre_gen_vsc();
     }
 
+    //the profile decides where outputs go, so it is selected before packages are set up
+    if args.release {
+        profiles::set_profile("release");
+    } else if let Some(profile) = &args.profile {
+        profiles::set_profile(profile);
+    }
+
+    //the toolchain decides which config is read and where outputs go
+    if let Some(triple) = &args.target {
+        toolchain::set_toolchain(toolchain::Toolchain::load(triple));
+    }
+
     let (build_config, targets, packages) = bin_flags::parse_config();
+    utils::log(
+        utils::LogLevel::Info,
+        &format!("Using profile: {}", build_config.profile().name),
+    );
+
+    //-j takes precedence over the jobs in the config
+    let job_limit = match &args.jobs {
+        Some(value) => Some(Jobs::parse(value).unwrap_or_else(|| {
+            utils::log(
+                utils::LogLevel::Error,
+                "--jobs must be a positive number or auto",
+            );
+            std::process::exit(1);
+        })),
+        None => build_config.jobs.clone(),
+    };
+    if let Some(job_limit) = &job_limit {
+        jobs::set_jobs(job_limit);
+    }
+    hasher::set_algorithm(build_config.hash);
+
+    //BUILDER_CPP_LAUNCHER takes precedence over the launcher in the config
+    if !args.no_launcher {
+        let env_launcher = std::env::var("BUILDER_CPP_LAUNCHER")
+            .ok()
+            .filter(|x| !x.trim().is_empty());
+        if let Some(launcher) = &env_launcher {
+            builder::set_launcher(launcher, "BUILDER_CPP_LAUNCHER");
+        } else if let Some(launcher) = &build_config.launcher {
+            builder::set_launcher(launcher, "build.launcher");
+        }
+    }
+
+    if let Some(cache_config) = &build_config.cache {
+        cache::set_cache(cache_config);
+    }
 
     if args.clean_packages {
         bin_flags::clean_packages(&packages);
@@ -0,0 +1,710 @@
+//! This module resolves the parts of a config that only apply to some platforms.
+//!
+//! One builder_cpp.toml can describe a project for every platform in two ways.
+//! `[target.'cfg(<condition>)']` tables are merged into the config when their condition holds,
+//! like `[target.'cfg(windows)'.build]` or `[target.'cfg(unix)'.targets.main]`, the latter
+//! changing the target named main. Arrays of targets in them, like
+//! `[[target.'cfg(windows)'.targets]]`, add targets only built on those platforms.
+//! Keys of build, targets, profiles, languages and the cache can also be given per platform,
+//! like `cflags.linux = "-pthread"` and `cflags.default = ""`, and take the value of the
+//! platform built for, then of unix, then the default, and are left out if none of them is given.
+//! Conditions are about the platform built for, which is the host unless cross compiling.
+use crate::toolchain::{self, Toolchain};
+use crate::utils::{log, LogLevel};
+use std::collections::HashMap;
+use toml::{Table, Value};
+
+/// The platforms names in conditions and per platform keys can be
+pub const PLATFORMS: [&str; 5] = ["linux", "android", "macos", "windows", "unix"];
+//keys whose value may be a table of their own
+const TABLE_KEYS: [&str; 1] = ["defines"];
+
+/// The keys conditions can compare with a value, like target_os = "linux"
+pub const CONDITION_KEYS: [&str; 3] = ["target_os", "target_family", "target"];
+
+/// A condition of a conditional table, like cfg(any(linux, macos))
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    /// Holds on a platform, one of PLATFORMS
+    Platform(String),
+    /// Holds if a key has a value, like target_os = "linux" or target = "aarch64-linux-gnu"
+    Equals(String, String),
+    Not(Box<Condition>),
+    Any(Vec<Condition>),
+    All(Vec<Condition>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Open,
+    Close,
+    Comma,
+    Equals,
+}
+
+impl Condition {
+    /// Parses a condition written like cfg(linux) or cfg(not(target_os = "windows"))
+    /// Returns None if the condition is invalid
+    /// # Arguments
+    /// * `text` - The condition
+    pub fn parse(text: &str) -> Option<Condition> {
+        let inner = text.trim().strip_prefix("cfg(")?.strip_suffix(')')?;
+        let tokens = tokenize(inner)?;
+        let mut pos = 0;
+        let condition = parse_condition(&tokens, &mut pos)?;
+        (pos == tokens.len()).then_some(condition)
+    }
+
+    /// Returns true if the condition holds for the platform a toolchain builds for
+    /// # Arguments
+    /// * `toolchain` - The toolchain
+    pub fn holds(&self, toolchain: &Toolchain) -> bool {
+        match self {
+            Condition::Platform(platform) => is_platform(platform, &toolchain.os),
+            Condition::Equals(key, value) => match key.as_str() {
+                "target_os" => toolchain.os == *value,
+                "target_family" => family(&toolchain.os) == value,
+                _ => toolchain.triple.as_deref() == Some(value.as_str()),
+            },
+            Condition::Not(condition) => !condition.holds(toolchain),
+            Condition::Any(conditions) => conditions.iter().any(|x| x.holds(toolchain)),
+            Condition::All(conditions) => conditions.iter().all(|x| x.holds(toolchain)),
+        }
+    }
+}
+
+/// Resolves the conditional tables and per platform keys of a config for the platform built for
+/// Returns the config and the key every target has in the file, like targets[1] for targets
+/// of the targets array and target.'cfg(windows)'.targets[0] for those added by a condition
+/// Exits if a condition is invalid or two tables that apply set the same key
+/// # Arguments
+/// * `config` - The whole config file
+/// * `path` - The path of the config file
+pub fn resolve(config: Table, path: &str) -> (Table, Vec<String>) {
+    resolve_for(config, toolchain::toolchain()).unwrap_or_else(|error| {
+        log(LogLevel::Error, &error.message);
+        log(LogLevel::Error, &format!("  In {}", path));
+        if let Some(hint) = error.hint {
+            log(LogLevel::Error, hint);
+        }
+        std::process::exit(1);
+    })
+}
+
+//an error in the conditional tables of a config
+#[derive(Debug)]
+struct Invalid {
+    message: String,
+    hint: Option<&'static str>,
+}
+
+impl Invalid {
+    fn new(message: String) -> Invalid {
+        Invalid {
+            message,
+            hint: None,
+        }
+    }
+}
+
+//resolves a config for the platform a toolchain builds for
+fn resolve_for(mut config: Table, toolchain: &Toolchain) -> Result<(Table, Vec<String>), Invalid> {
+    let target_count = match config.get("targets") {
+        Some(Value::Array(targets)) => targets.len(),
+        _ => 0,
+    };
+    let mut target_keys: Vec<String> = (0..target_count)
+        .map(|x| format!("targets[{}]", x))
+        .collect();
+    if let Some(conditionals) = config.remove("target") {
+        let Value::Table(conditionals) = conditionals else {
+            return Err(Invalid::new("target is not a table".to_string()));
+        };
+        //the condition every key was set by, so that conflicting tables are reported
+        let mut set_by = HashMap::new();
+        for (text, overrides) in conditionals {
+            let Some(condition) = Condition::parse(&text) else {
+                return Err(Invalid {
+                    message: format!("Invalid condition: {}", text),
+                    hint: Some("Conditions look like cfg(linux), cfg(not(windows)) or cfg(any(target_os = \"linux\", macos))"),
+                });
+            };
+            let Value::Table(overrides) = overrides else {
+                return Err(Invalid::new(format!("target.'{}' is not a table", text)));
+            };
+            if !condition.holds(toolchain) {
+                continue;
+            }
+            let mut merge = Merge {
+                condition: &text,
+                set_by: &mut set_by,
+                target_keys: &mut target_keys,
+            };
+            for (key, value) in overrides {
+                if key == "targets" {
+                    merge.targets(&mut config, value)?;
+                } else {
+                    merge.value(&mut config, key.clone(), value, key)?;
+                }
+            }
+        }
+    }
+    let os = &toolchain.os;
+    for key in ["build", "cache"] {
+        if let Some(Value::Table(table)) = config.get_mut(key) {
+            resolve_keys(table, os);
+        }
+    }
+    for key in ["profile", "languages"] {
+        if let Some(Value::Table(tables)) = config.get_mut(key) {
+            for (_, table) in tables.iter_mut() {
+                if let Value::Table(table) = table {
+                    resolve_keys(table, os);
+                }
+            }
+        }
+    }
+    if let Some(Value::Array(targets)) = config.get_mut("targets") {
+        for target in targets {
+            if let Value::Table(target) = target {
+                resolve_keys(target, os);
+            }
+        }
+    }
+    Ok((config, target_keys))
+}
+
+//merges the tables of a condition that holds into the config
+struct Merge<'a> {
+    condition: &'a str,
+    set_by: &'a mut HashMap<String, String>,
+    target_keys: &'a mut Vec<String>,
+}
+
+impl Merge<'_> {
+    //sets a key of a table, merging tables into the tables already there
+    fn value(
+        &mut self,
+        table: &mut Table,
+        key: String,
+        value: Value,
+        key_path: String,
+    ) -> Result<(), Invalid> {
+        if let Value::Table(value) = value {
+            let existing = table
+                .entry(key.clone())
+                .or_insert_with(|| Value::Table(Table::new()));
+            if let Value::Table(existing) = existing {
+                for (sub_key, sub_value) in value {
+                    let sub_path = format!("{}.{}", key_path, sub_key);
+                    self.value(existing, sub_key, sub_value, sub_path)?;
+                }
+                return Ok(());
+            }
+            table.insert(key, Value::Table(value));
+        } else {
+            table.insert(key, value);
+        }
+        match self
+            .set_by
+            .insert(key_path.clone(), self.condition.to_string())
+        {
+            Some(other) if other != self.condition => Err(Invalid::new(format!(
+                "{} is set by both target.'{}' and target.'{}'",
+                key_path, other, self.condition
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    //changes the targets named in a table, or adds the targets in an array
+    fn targets(&mut self, config: &mut Table, value: Value) -> Result<(), Invalid> {
+        let targets = config
+            .entry("targets".to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+        let Value::Array(targets) = targets else {
+            return Ok(());
+        };
+        match value {
+            Value::Array(added) => {
+                self.target_keys.extend(
+                    (0..added.len()).map(|x| format!("target.'{}'.targets[{}]", self.condition, x)),
+                );
+                targets.extend(added);
+            }
+            Value::Table(changed) => {
+                for (name, overrides) in changed {
+                    let target = targets.iter_mut().find_map(|x| match x {
+                        Value::Table(x)
+                            if x.get("name").and_then(|x| x.as_str()) == Some(&name) =>
+                        {
+                            Some(x)
+                        }
+                        _ => None,
+                    });
+                    let (Some(target), Value::Table(overrides)) = (target, overrides) else {
+                        return Err(Invalid {
+                            message: format!(
+                                "target.'{}'.targets.{} does not change a target",
+                                self.condition, name
+                            ),
+                            hint: Some(
+                                "It has to be a table of keys to change in the target with that name",
+                            ),
+                        });
+                    };
+                    for (key, value) in overrides {
+                        let key_path = format!("targets.{}.{}", name, key);
+                        self.value(target, key, value, key_path)?;
+                    }
+                }
+            }
+            _ => {
+                return Err(Invalid::new(format!(
+                    "target.'{}'.targets is neither a table nor an array",
+                    self.condition
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+//replaces the keys of a table given per platform with the value of the platform built for,
+//also in the tables of arrays, like the deps of a target
+fn resolve_keys(table: &mut Table, os: &str) {
+    let keys: Vec<String> = table.keys().cloned().collect();
+    for key in keys {
+        match table.get_mut(&key) {
+            Some(Value::Table(platforms)) if is_per_platform(&key, platforms) => {
+                match platform_value(platforms, os) {
+                    Some(value) => table.insert(key, value),
+                    None => table.remove(&key),
+                };
+            }
+            Some(Value::Array(values)) => {
+                for value in values {
+                    if let Value::Table(value) = value {
+                        resolve_keys(value, os);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+//returns true if every key of a table is a platform or default
+//keys that hold a table themselves, like defines, are only given per platform
+//with tables or arrays as values, which their own tables never hold
+fn is_per_platform(key: &str, table: &Table) -> bool {
+    let keys_are_platforms = !table.is_empty()
+        && table
+            .keys()
+            .all(|x| x == "default" || PLATFORMS.contains(&x.as_str()));
+    if !TABLE_KEYS.contains(&key) {
+        return keys_are_platforms;
+    }
+    keys_are_platforms
+        && table
+            .values()
+            .all(|x| matches!(x, Value::Table(_) | Value::Array(_)))
+}
+
+//returns the value of an operating system, then of its family and then the default
+fn platform_value(platforms: &Table, os: &str) -> Option<Value> {
+    platforms
+        .get(os)
+        .or_else(|| platforms.get(family(os)))
+        .or_else(|| platforms.get("default"))
+        .cloned()
+}
+
+//returns true if an operating system is or belongs to a platform
+fn is_platform(platform: &str, os: &str) -> bool {
+    platform == os || platform == family(os)
+}
+
+//returns the family of an operating system, unix or windows
+fn family(os: &str) -> &'static str {
+    if os == "windows" {
+        "windows"
+    } else {
+        "unix"
+    }
+}
+
+//splits the inside of cfg(...) into tokens, returns None on characters that cannot appear in it
+fn tokenize(text: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '(' => tokens.push(Token::Open),
+            ')' => tokens.push(Token::Close),
+            ',' => tokens.push(Token::Comma),
+            '=' => tokens.push(Token::Equals),
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        c => value.push(c),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_whitespace() => {}
+            c if c.is_ascii_alphanumeric() || c == '_' => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|x| x.is_ascii_alphanumeric() || *x == '_') {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+            _ => return None,
+        }
+    }
+    Some(tokens)
+}
+
+//parses the condition starting at pos, and moves pos past it
+fn parse_condition(tokens: &[Token], pos: &mut usize) -> Option<Condition> {
+    let Some(Token::Word(word)) = tokens.get(*pos) else {
+        return None;
+    };
+    *pos += 1;
+    match tokens.get(*pos) {
+        Some(Token::Open) if ["not", "any", "all"].contains(&word.as_str()) => {
+            *pos += 1;
+            let mut conditions = Vec::new();
+            loop {
+                if tokens.get(*pos) == Some(&Token::Close) {
+                    *pos += 1;
+                    break;
+                }
+                conditions.push(parse_condition(tokens, pos)?);
+                match tokens.get(*pos)? {
+                    Token::Comma => *pos += 1,
+                    Token::Close => {
+                        *pos += 1;
+                        break;
+                    }
+                    _ => return None,
+                }
+            }
+            match word.as_str() {
+                "not" if conditions.len() == 1 => {
+                    Some(Condition::Not(Box::new(conditions.remove(0))))
+                }
+                "any" => Some(Condition::Any(conditions)),
+                "all" => Some(Condition::All(conditions)),
+                _ => None,
+            }
+        }
+        Some(Token::Equals) if CONDITION_KEYS.contains(&word.as_str()) => {
+            let Some(Token::Str(value)) = tokens.get(*pos + 1) else {
+                return None;
+            };
+            *pos += 2;
+            Some(Condition::Equals(word.clone(), value.clone()))
+        }
+        _ if PLATFORMS.contains(&word.as_str()) => Some(Condition::Platform(word.clone())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn toolchain(os: &str, triple: Option<&str>) -> Toolchain {
+        let mut toolchain = Toolchain::host();
+        toolchain.os = os.to_string();
+        toolchain.triple = triple.map(String::from);
+        toolchain
+    }
+
+    fn holds(condition: &str, toolchain: &Toolchain) -> bool {
+        Condition::parse(condition).unwrap().holds(toolchain)
+    }
+
+    fn resolve(config: &str, toolchain: &Toolchain) -> (Table, Vec<String>) {
+        resolve_for(config.parse().unwrap(), toolchain).unwrap()
+    }
+
+    fn error(config: &str, toolchain: &Toolchain) -> String {
+        resolve_for(config.parse().unwrap(), toolchain)
+            .unwrap_err()
+            .message
+    }
+
+    fn get<'a>(table: &'a Table, path: &str) -> Option<&'a Value> {
+        let mut value = table.get(path.split('.').next()?)?;
+        for key in path.split('.').skip(1) {
+            value = value.get(key)?;
+        }
+        Some(value)
+    }
+
+    #[test]
+    fn conditions_are_parsed() {
+        assert_eq!(
+            Condition::parse("cfg(linux)"),
+            Some(Condition::Platform("linux".to_string()))
+        );
+        assert_eq!(
+            Condition::parse(" cfg( target_os = \"windows\" ) "),
+            Some(Condition::Equals(
+                "target_os".to_string(),
+                "windows".to_string()
+            ))
+        );
+        assert_eq!(
+            Condition::parse("cfg(not(any(windows, all(unix, target = \"aarch64-linux-gnu\"))))"),
+            Some(Condition::Not(Box::new(Condition::Any(vec![
+                Condition::Platform("windows".to_string()),
+                Condition::All(vec![
+                    Condition::Platform("unix".to_string()),
+                    Condition::Equals("target".to_string(), "aarch64-linux-gnu".to_string()),
+                ]),
+            ]))))
+        );
+        assert_eq!(Condition::parse("cfg(any())"), Some(Condition::Any(vec![])));
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in [
+            "linux",
+            "cfg()",
+            "cfg(lunix)",
+            "cfg(linux",
+            "cfg(linux))",
+            "cfg(linux windows)",
+            "cfg(linux, windows)",
+            "cfg(linux) garbage",
+            "cfg(not(linux) windows)",
+            "cfg(not(linux, windows))",
+            "cfg(not())",
+            "cfg(any(linux,, windows))",
+            "cfg(target_os)",
+            "cfg(target_os = linux)",
+            "cfg(target_os = \"linux)",
+            "cfg(target_arch = \"x86_64\")",
+            "cfg(linux = \"x\")",
+            "cfg(maybe(linux))",
+            "cfg(linux && windows)",
+        ] {
+            assert_eq!(
+                Condition::parse(condition),
+                None,
+                "{} was accepted",
+                condition
+            );
+        }
+    }
+
+    #[test]
+    fn conditions_hold_on_their_platforms() {
+        let linux = toolchain("linux", None);
+        let android = toolchain("android", Some("aarch64-linux-android"));
+        let windows = toolchain("windows", Some("x86_64-w64-mingw32"));
+        assert!(holds("cfg(linux)", &linux));
+        assert!(holds("cfg(unix)", &linux));
+        assert!(holds("cfg(unix)", &android));
+        assert!(!holds("cfg(linux)", &android));
+        assert!(holds("cfg(windows)", &windows));
+        assert!(!holds("cfg(unix)", &windows));
+        assert!(holds("cfg(target_os = \"android\")", &android));
+        assert!(holds("cfg(target_family = \"unix\")", &android));
+        assert!(holds("cfg(target_family = \"windows\")", &windows));
+        assert!(holds("cfg(target = \"x86_64-w64-mingw32\")", &windows));
+        assert!(!holds("cfg(target = \"x86_64-w64-mingw32\")", &linux));
+    }
+
+    #[test]
+    fn nested_conditions_combine() {
+        let linux = toolchain("linux", None);
+        let windows = toolchain("windows", Some("x86_64-w64-mingw32"));
+        let condition = "cfg(all(unix, not(target_os = \"android\")))";
+        assert!(holds(condition, &linux));
+        assert!(!holds(condition, &windows));
+        let condition = "cfg(any(macos, all(windows, target = \"x86_64-w64-mingw32\")))";
+        assert!(!holds(condition, &linux));
+        assert!(holds(condition, &windows));
+        assert!(holds("cfg(not(not(linux)))", &linux));
+        assert!(!holds("cfg(any())", &linux));
+        assert!(holds("cfg(all())", &linux));
+    }
+
+    #[test]
+    fn per_platform_keys_fall_back_to_the_family_then_the_default() {
+        let config = r#"
+            [build]
+            compiler.windows = "x86_64-w64-mingw32-g++"
+            compiler.default = "g++"
+
+            [[targets]]
+            name = "main"
+            cflags.linux = "-pthread"
+            cflags.unix = "-DUNIX"
+            cflags.default = ""
+            libs.windows = "-lws2_32"
+            deps = [{ name = "libengine", visibility.linux = "public", visibility.default = "private" }]
+        "#;
+        let (linux, _) = resolve(config, &toolchain("linux", None));
+        assert_eq!(get(&linux, "build.compiler").unwrap().as_str(), Some("g++"));
+        let target = &linux["targets"][0];
+        assert_eq!(target["cflags"].as_str(), Some("-pthread"));
+        assert_eq!(target.get("libs"), None);
+        assert_eq!(target["deps"][0]["visibility"].as_str(), Some("public"));
+
+        let (android, _) = resolve(config, &toolchain("android", Some("aarch64-linux-android")));
+        let target = &android["targets"][0];
+        assert_eq!(target["cflags"].as_str(), Some("-DUNIX"));
+        assert_eq!(target["deps"][0]["visibility"].as_str(), Some("private"));
+
+        let (windows, _) = resolve(config, &toolchain("windows", Some("x86_64-w64-mingw32")));
+        let compiler = get(&windows, "build.compiler").unwrap().as_str();
+        assert_eq!(compiler, Some("x86_64-w64-mingw32-g++"));
+        let target = &windows["targets"][0];
+        assert_eq!(target["cflags"].as_str(), Some(""));
+        assert_eq!(target["libs"].as_str(), Some("-lws2_32"));
+    }
+
+    #[test]
+    fn tables_with_other_keys_are_left_alone() {
+        let config = r#"
+            [[targets]]
+            name = "main"
+            defines = { linux = "1", DEBUG = "1" }
+        "#;
+        let (config, _) = resolve(config, &toolchain("linux", None));
+        let defines = config["targets"][0]["defines"].as_table().unwrap();
+        assert_eq!(defines.len(), 2);
+    }
+
+    #[test]
+    fn defines_named_like_platforms_are_not_per_platform() {
+        let config = r#"
+            [[targets]]
+            name = "main"
+            defines = { linux = 1, unix = 1 }
+
+            [[targets]]
+            name = "libengine"
+            defines.linux = { USE_EPOLL = true }
+            defines.default = ["PORTABLE"]
+        "#;
+        let (linux, _) = resolve(config, &toolchain("linux", None));
+        let defines = linux["targets"][0]["defines"].as_table().unwrap();
+        assert_eq!(defines.len(), 2);
+        let defines = linux["targets"][1]["defines"].as_table().unwrap();
+        assert_eq!(defines["USE_EPOLL"].as_bool(), Some(true));
+
+        let (windows, _) = resolve(config, &toolchain("windows", Some("x86_64-w64-mingw32")));
+        let defines = windows["targets"][1]["defines"].as_array().unwrap();
+        assert_eq!(defines[0].as_str(), Some("PORTABLE"));
+    }
+
+    #[test]
+    fn conditional_tables_merge_when_they_hold() {
+        let config = r#"
+            [build]
+            compiler = "g++"
+
+            [[targets]]
+            name = "main"
+            cflags = "-g"
+
+            [target.'cfg(windows)'.build]
+            compiler = "x86_64-w64-mingw32-g++"
+
+            [target.'cfg(unix)'.targets.main]
+            cflags = "-g -DUNIX"
+
+            [target.'cfg(not(windows))'.profile.release]
+            cflags = "-O2"
+        "#;
+        let (linux, _) = resolve(config, &toolchain("linux", None));
+        assert_eq!(linux["build"]["compiler"].as_str(), Some("g++"));
+        assert_eq!(linux["targets"][0]["cflags"].as_str(), Some("-g -DUNIX"));
+        assert_eq!(
+            get(&linux, "profile.release.cflags").unwrap().as_str(),
+            Some("-O2")
+        );
+        assert!(linux.get("target").is_none());
+
+        let (windows, _) = resolve(config, &toolchain("windows", Some("x86_64-w64-mingw32")));
+        let compiler = windows["build"]["compiler"].as_str();
+        assert_eq!(compiler, Some("x86_64-w64-mingw32-g++"));
+        assert_eq!(windows["targets"][0]["cflags"].as_str(), Some("-g"));
+        assert!(windows.get("profile").is_none());
+    }
+
+    #[test]
+    fn conditional_targets_are_added_with_their_keys() {
+        let config = r#"
+            [[targets]]
+            name = "main"
+
+            [[targets]]
+            name = "libengine"
+
+            [[target.'cfg(unix)'.targets]]
+            name = "daemon"
+
+            [[target.'cfg(windows)'.targets]]
+            name = "service"
+        "#;
+        let (linux, keys) = resolve(config, &toolchain("linux", None));
+        let names: Vec<&str> = linux["targets"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(names, ["main", "libengine", "daemon"]);
+        assert_eq!(
+            keys,
+            ["targets[0]", "targets[1]", "target.'cfg(unix)'.targets[0]"]
+        );
+    }
+
+    #[test]
+    fn conditions_setting_the_same_key_conflict() {
+        let config = r#"
+            [[targets]]
+            name = "main"
+
+            [target.'cfg(linux)'.targets.main]
+            cflags = "-DLINUX"
+
+            [target.'cfg(unix)'.targets.main]
+            cflags = "-DUNIX"
+        "#;
+        assert_eq!(
+            error(config, &toolchain("linux", None)),
+            "targets.main.cflags is set by both target.'cfg(linux)' and target.'cfg(unix)'"
+        );
+        let (android, _) = resolve(config, &toolchain("android", Some("aarch64-linux-android")));
+        assert_eq!(android["targets"][0]["cflags"].as_str(), Some("-DUNIX"));
+    }
+
+    #[test]
+    fn invalid_conditional_tables_are_reported() {
+        let linux = toolchain("linux", None);
+        assert_eq!(
+            error("[target.'cfg(lunix)'.build]\ncompiler = \"g++\"", &linux),
+            "Invalid condition: cfg(lunix)"
+        );
+        assert_eq!(
+            error("[target.'cfg(unix)'.targets.nope]\ncflags = \"-g\"", &linux),
+            "target.'cfg(unix)'.targets.nope does not change a target"
+        );
+        assert_eq!(error("target = 1", &linux), "target is not a table");
+    }
+}
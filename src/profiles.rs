@@ -0,0 +1,280 @@
+//! This module contains the build profiles and the directories their outputs are placed in.
+//!
+//! A profile adds its flags on top of the flags of every target, and sets the optimization
+//! of the targets that do not set their own. The builtin profiles are
+//! debug (the default) and release. They can be changed, and new ones added, with
+//! `[profile.<name>]` tables in the config.
+//! Every profile has its own bin and obj directories and hash files under `.bld_cpp/<name>`,
+//! so switching between profiles does not rebuild what was already built with them.
+//! Cross compiled outputs go to `.bld_cpp/<name>/<triple>` instead.
+use crate::flags;
+use crate::toolchain;
+use crate::utils::{log, LogLevel};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+/// A set of flags added to every target
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Flags passed when compiling sources of every language
+    pub cflags: String,
+    /// Flags passed when compiling c++ sources only
+    pub cxxflags: String,
+    /// Flags passed when linking
+    pub ldflags: String,
+    /// One of flags::OPTIMIZATION_LEVELS, used for targets without their own
+    pub optimization: Option<String>,
+}
+
+static PROFILE: OnceLock<String> = OnceLock::new();
+
+impl Profile {
+    /// Returns the profiles known without any config
+    pub fn builtin() -> Vec<Profile> {
+        let profile = |name: &str, cflags: &str, optimization: &str| Profile {
+            name: name.to_string(),
+            cflags: cflags.to_string(),
+            cxxflags: String::new(),
+            ldflags: String::new(),
+            optimization: Some(optimization.to_string()),
+        };
+        vec![
+            profile("debug", "-g", "0"),
+            profile("release", "-DNDEBUG", "2"),
+        ]
+    }
+
+    /// Parses the profile tables of a config on top of the builtin profiles
+    /// # Arguments
+    /// * `config` - The whole config file
+    pub fn parse_profiles(config: &Table) -> Vec<Profile> {
+        let mut profiles = Profile::builtin();
+        let profiles_toml = match config.get("profile") {
+            Some(Value::Table(profiles_toml)) => profiles_toml,
+            Some(_) => {
+                log(LogLevel::Error, "profile is not a table");
+                std::process::exit(1);
+            }
+            None => return profiles,
+        };
+        for (name, profile_toml) in profiles_toml {
+            check_name(name);
+            let get_str = |key: &str| {
+                profile_toml.get(key).map(|x| {
+                    x.as_str()
+                        .unwrap_or_else(|| {
+                            log(
+                                LogLevel::Error,
+                                &format!("{} of profile {} is not a string", key, name),
+                            );
+                            std::process::exit(1);
+                        })
+                        .to_string()
+                })
+            };
+            let cflags = get_str("cflags");
+            let cxxflags = get_str("cxxflags");
+            let ldflags = get_str("ldflags");
+            let optimization = profile_toml.get("optimization").map(flags::parse_optimization);
+            match profiles.iter_mut().find(|x| x.name == *name) {
+                Some(profile) => {
+                    if let Some(cflags) = cflags {
+                        profile.cflags = cflags;
+                    }
+                    if let Some(cxxflags) = cxxflags {
+                        profile.cxxflags = cxxflags;
+                    }
+                    if let Some(ldflags) = ldflags {
+                        profile.ldflags = ldflags;
+                    }
+                    if optimization.is_some() {
+                        profile.optimization = optimization;
+                    }
+                }
+                None => profiles.push(Profile {
+                    name: name.clone(),
+                    cflags: cflags.unwrap_or_default(),
+                    cxxflags: cxxflags.unwrap_or_default(),
+                    ldflags: ldflags.unwrap_or_default(),
+                    optimization,
+                }),
+            }
+        }
+        profiles
+    }
+}
+
+/// Selects the profile to build with, debug is used if none is selected
+/// # Arguments
+/// * `name` - The name of the profile
+pub fn set_profile(name: &str) {
+    check_name(name);
+    if PROFILE.set(name.to_string()).is_err() {
+        log(LogLevel::Warn, "Profile already selected");
+    }
+}
+
+/// Returns the name of the selected profile
+pub fn profile_name() -> &'static str {
+    PROFILE.get().map(|x| x.as_str()).unwrap_or("debug")
+}
+
+/// Returns the directory the outputs of the selected profile and toolchain are placed in
+/// This is .bld_cpp/<profile>, or .bld_cpp/<profile>/<triple> when cross compiling
+pub fn profile_dir() -> String {
+    match &toolchain::toolchain().triple {
+        Some(triple) => format!(".bld_cpp/{}/{}", profile_name(), triple),
+        None => format!(".bld_cpp/{}", profile_name()),
+    }
+}
+
+/// Returns the directory the binaries of the selected profile are placed in
+pub fn build_dir() -> String {
+    format!("{}/bin", profile_dir())
+}
+
+/// Returns the directory the objects of the selected profile are placed in
+pub fn obj_dir() -> String {
+    format!("{}/obj_{}", profile_dir(), platform_name())
+}
+
+/// Returns the directory the unity batches of the selected profile are generated in
+pub fn unity_dir() -> String {
+    format!("{}/unity", profile_dir())
+}
+
+/// Returns the path of the hash file of a target in the selected profile
+/// # Arguments
+/// * `target_name` - The name of the target
+pub fn hash_file_path(target_name: &str) -> String {
+    format!(
+        "{}/{}.{}.hashes",
+        profile_dir(),
+        target_name,
+        platform_name()
+    )
+}
+
+/// Removes what builds placed directly in `.bld_cpp` before outputs were placed per profile:
+/// the bin and obj directories and the hash files of every target.
+/// Nothing in them is reused, as objects and binaries now have other paths,
+/// so once they are gone this does nothing.
+pub fn remove_legacy_outputs() {
+    for path in remove_legacy_outputs_in(Path::new(".bld_cpp")) {
+        log(LogLevel::Info, &format!("Cleaning: {}", path.display()));
+    }
+}
+
+//removes the legacy outputs under root and returns their paths
+fn remove_legacy_outputs_in(root: &Path) -> Vec<PathBuf> {
+    let mut removed = Vec::new();
+    for dir in ["bin", "obj_linux", "obj_win32"] {
+        let dir = root.join(dir);
+        if !dir.is_dir() {
+            continue;
+        }
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => removed.push(dir),
+            Err(why) => log(
+                LogLevel::Warn,
+                &format!("Could not remove {}: {}", dir.display(), why),
+            ),
+        }
+    }
+    let Ok(entries) = fs::read_dir(root) else {
+        return removed;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !path.is_file() || !(name.ends_with(".linux.hash") || name.ends_with(".win32.hash")) {
+            continue;
+        }
+        match fs::remove_file(&path) {
+            Ok(()) => removed.push(path),
+            Err(why) => log(
+                LogLevel::Warn,
+                &format!("Could not remove {}: {}", path.display(), why),
+            ),
+        }
+    }
+    removed.sort();
+    removed
+}
+
+//returns the name of the platform built for in the names of obj dirs and hash files
+fn platform_name() -> &'static str {
+    if toolchain::toolchain().is_windows() {
+        "win32"
+    } else {
+        "linux"
+    }
+}
+
+//exits if the name of a profile cannot be used as its directory
+fn check_name(name: &str) {
+    let reserved = ["bin", "obj_linux", "obj_win32", "sources", "includes"];
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-')
+        && !reserved.contains(&name);
+    if !valid {
+        log(
+            LogLevel::Error,
+            &format!("Invalid profile name: {}", name),
+        );
+        log(
+            LogLevel::Error,
+            "Profile names are letters, digits, - and _, and cannot be bin, obj_linux, obj_win32, sources or includes",
+        );
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_outputs_are_removed_and_current_ones_kept() {
+        let root = std::env::temp_dir().join(format!("bld_cpp_legacy_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        for dir in ["bin", "obj_linux/main", "obj_win32", "debug/bin", "sources/pkg"] {
+            fs::create_dir_all(root.join(dir)).unwrap();
+        }
+        for file in [
+            "bin/main",
+            "obj_linux/main/main.o",
+            "main.linux.hash",
+            "mathlib.win32.hash",
+            "debug/main.linux.hashes",
+        ] {
+            fs::write(root.join(file), "").unwrap();
+        }
+
+        let removed = remove_legacy_outputs_in(&root);
+        let mut expected: Vec<PathBuf> = [
+            "bin",
+            "main.linux.hash",
+            "mathlib.win32.hash",
+            "obj_linux",
+            "obj_win32",
+        ]
+        .iter()
+        .map(|name| root.join(name))
+        .collect();
+        expected.sort();
+        assert_eq!(removed, expected);
+        assert!(root.join("debug/main.linux.hashes").exists());
+        assert!(root.join("debug/bin").exists());
+        assert!(root.join("sources/pkg").exists());
+        //nothing is left to remove the second time
+        assert!(remove_legacy_outputs_in(&root).is_empty());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
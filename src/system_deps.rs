@@ -0,0 +1,376 @@
+//! This module finds the libraries targets take from the system, using pkg-config.
+//!
+//! A system dep is the name of a pkg-config package, optionally followed by a version
+//! constraint, like `freetype2 >= 2.10`. pkg-config is asked once per build for the version,
+//! compile flags and link flags of every package. The `PKG_CONFIG` environment variable
+//! selects another pkg-config, which finds packages in `PKG_CONFIG_PATH` as usual.
+//! When cross compiling, the toolchain selects the pkg-config and the directories of the
+//! packages of the target, which are in its sysroot.
+use crate::toolchain;
+use crate::utils::{log, LogLevel};
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::process::Command;
+use std::sync::{LazyLock, Mutex};
+
+/// The comparisons a version constraint can use
+pub const VERSION_OPERATORS: [&str; 6] = [">=", "<=", ">", "<", "=", "!="];
+
+//the packages pkg-config was asked about in this build, by name
+static PACKAGES: LazyLock<Mutex<HashMap<String, Package>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// A library a target takes from the system
+#[derive(Debug, Clone)]
+pub struct SystemDep {
+    /// The name of the pkg-config package
+    pub name: String,
+    /// The operator and version the package has to satisfy, if any
+    pub constraint: Option<(String, String)>,
+}
+
+/// What pkg-config knows about a package
+#[derive(Debug, Clone)]
+pub struct Package {
+    pub version: String,
+    /// Flags passed when compiling, like -I and -D flags
+    pub cflags: Vec<String>,
+    /// Flags passed when linking, like -L and -l flags
+    pub libs: Vec<String>,
+}
+
+impl SystemDep {
+    /// Parses a system dep from the config
+    /// Exits if the constraint is not an operator followed by a version
+    /// # Arguments
+    /// * `spec` - The dep, like glfw3 or freetype2 >= 2.10
+    /// * `origin` - Where the dep is declared
+    pub fn parse(spec: &str, origin: &str) -> SystemDep {
+        parse_spec(spec).unwrap_or_else(|| {
+            log(LogLevel::Error, &format!("Invalid system dep: {}", spec));
+            log(LogLevel::Error, &format!("  In {}", origin));
+            log(
+                LogLevel::Error,
+                &format!(
+                    "System deps are a name, optionally followed by one of {} and a version",
+                    VERSION_OPERATORS.join(", ")
+                ),
+            );
+            std::process::exit(1);
+        })
+    }
+
+    /// Finds the package of the dep with pkg-config and checks its version
+    /// Exits if pkg-config cannot find the package or its version does not satisfy the constraint
+    /// # Arguments
+    /// * `origin` - Where the dep is declared, see TargetConfig::origin
+    pub fn find(&self, origin: &str) -> Package {
+        //held while pkg-config runs, so that targets built in parallel do not ask twice
+        let mut packages = PACKAGES.lock().unwrap();
+        let package = match packages.get(&self.name) {
+            Some(package) => package.clone(),
+            None => {
+                check_toolchain(self, origin);
+                let package = Package {
+                    version: pkg_config(&["--modversion", &self.name], self, origin).join(" "),
+                    cflags: pkg_config(&["--cflags", &self.name], self, origin),
+                    libs: pkg_config(&["--libs", &self.name], self, origin),
+                };
+                log(
+                    LogLevel::Info,
+                    &format!("Found system dep: {} {}", self.name, package.version),
+                );
+                packages.insert(self.name.clone(), package.clone());
+                package
+            }
+        };
+        if let Some((operator, version)) = &self.constraint {
+            if !satisfies(&package.version, operator, version) {
+                log(
+                    LogLevel::Error,
+                    &format!(
+                        "System dep {} {} {} is required, but version {} was found",
+                        self.name, operator, version, package.version
+                    ),
+                );
+                log(LogLevel::Error, &format!("  In {}", origin));
+                std::process::exit(1);
+            }
+        }
+        package
+    }
+}
+
+impl Package {
+    /// Returns the include dirs among the compile flags
+    pub fn include_dirs(&self) -> Vec<String> {
+        let mut include_dirs = Vec::new();
+        let mut args = self.cflags.iter();
+        while let Some(arg) = args.next() {
+            if arg == "-I" || arg == "-isystem" {
+                include_dirs.extend(args.next().cloned());
+            } else if let Some(dir) = arg.strip_prefix("-I") {
+                include_dirs.push(dir.to_string());
+            } else if let Some(dir) = arg.strip_prefix("-isystem") {
+                include_dirs.push(dir.to_string());
+            }
+        }
+        include_dirs
+    }
+}
+
+//exits if the dep would be looked for among the packages of the host while cross compiling
+fn check_toolchain(dep: &SystemDep, origin: &str) {
+    let toolchain = toolchain::toolchain();
+    let Some(triple) = &toolchain.triple else {
+        return;
+    };
+    if toolchain.pkg_config.is_some() || toolchain.pkg_config_libdir.is_some() {
+        return;
+    }
+    log(
+        LogLevel::Error,
+        &format!(
+            "System dep {} cannot be found for {} without a sysroot",
+            dep.name, triple
+        ),
+    );
+    log(LogLevel::Error, &format!("  In {}", origin));
+    let path = toolchain
+        .path
+        .clone()
+        .unwrap_or_else(|| format!("./toolchains/{}.toml", triple));
+    log(
+        LogLevel::Error,
+        &format!(
+            "Set sysroot, pkg_config or pkg_config_libdir in {}, so that the packages of the host are not used",
+            path
+        ),
+    );
+    std::process::exit(1);
+}
+
+//runs pkg-config and splits its output into arguments, exits if the package cannot be found
+fn pkg_config(args: &[&str], dep: &SystemDep, origin: &str) -> Vec<String> {
+    let toolchain = toolchain::toolchain();
+    let program = toolchain.pkg_config.clone().unwrap_or_else(|| {
+        std::env::var("PKG_CONFIG").unwrap_or_else(|_| "pkg-config".to_string())
+    });
+    let mut cmd = Command::new(&program);
+    cmd.args(args);
+    if let Some(libdir) = &toolchain.pkg_config_libdir {
+        cmd.env("PKG_CONFIG_LIBDIR", libdir);
+    }
+    if let Some(sysroot_dir) = &toolchain.pkg_config_sysroot_dir {
+        cmd.env("PKG_CONFIG_SYSROOT_DIR", sysroot_dir);
+    }
+    let output = cmd.output().unwrap_or_else(|why| {
+        log(
+            LogLevel::Error,
+            &format!("Could not run {} to find system dep: {}", program, dep.name),
+        );
+        log(LogLevel::Error, &format!("  In {}", origin));
+        log(LogLevel::Error, &format!("  Error: {}", why));
+        std::process::exit(1);
+    });
+    if !output.status.success() {
+        log(
+            LogLevel::Error,
+            &format!("Could not find system dep: {}", dep.name),
+        );
+        log(LogLevel::Error, &format!("  In {}", origin));
+        for line in String::from_utf8_lossy(&output.stderr).lines() {
+            log(LogLevel::Error, &format!("  {}", line));
+        }
+        log(
+            LogLevel::Error,
+            "Install its development package, or add the directory of its .pc file to PKG_CONFIG_PATH",
+        );
+        std::process::exit(1);
+    }
+    shell_words::split(&String::from_utf8_lossy(&output.stdout)).unwrap_or_else(|why| {
+        log(
+            LogLevel::Error,
+            &format!(
+                "Could not parse the output of {} for: {}",
+                program, dep.name
+            ),
+        );
+        log(LogLevel::Error, &format!("  Error: {}", why));
+        std::process::exit(1);
+    })
+}
+
+//splits a dep into its name and constraint, returns None if it is invalid
+fn parse_spec(spec: &str) -> Option<SystemDep> {
+    let is_operator = |c: char| ['<', '>', '=', '!'].contains(&c);
+    let (name, constraint) = match spec.find(is_operator) {
+        Some(index) => {
+            let (name, constraint) = spec.split_at(index);
+            let version = constraint.trim_start_matches(is_operator);
+            let operator = &constraint[..constraint.len() - version.len()];
+            let version = version.trim();
+            if !VERSION_OPERATORS.contains(&operator)
+                || version.is_empty()
+                || version.contains(|c: char| c.is_whitespace() || is_operator(c))
+            {
+                return None;
+            }
+            (
+                name.trim(),
+                Some((operator.to_string(), version.to_string())),
+            )
+        }
+        None => (spec.trim(), None),
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(SystemDep {
+        name: name.to_string(),
+        constraint,
+    })
+}
+
+//returns true if a version satisfies the constraint of an operator and a version
+fn satisfies(found: &str, operator: &str, version: &str) -> bool {
+    let ordering = compare_versions(found, version);
+    match operator {
+        ">=" => ordering != Ordering::Less,
+        "<=" => ordering != Ordering::Greater,
+        ">" => ordering == Ordering::Greater,
+        "<" => ordering == Ordering::Less,
+        "=" => ordering == Ordering::Equal,
+        _ => ordering != Ordering::Equal,
+    }
+}
+
+//compares versions the way pkg-config does, segment by segment,
+//where numbers compare by value and are newer than letters
+fn compare_versions(a: &str, b: &str) -> Ordering {
+    let segments = |version: &str| -> Vec<String> {
+        let mut segments: Vec<String> = Vec::new();
+        let mut last: Option<bool> = None;
+        for c in version.chars() {
+            if !c.is_ascii_alphanumeric() {
+                last = None;
+                continue;
+            }
+            let is_digit = c.is_ascii_digit();
+            match segments.last_mut() {
+                Some(segment) if last == Some(is_digit) => segment.push(c),
+                _ => segments.push(c.to_string()),
+            }
+            last = Some(is_digit);
+        }
+        segments
+    };
+    let (a, b) = (segments(a), segments(b));
+    for (x, y) in a.iter().zip(&b) {
+        let x_is_number = x.starts_with(|c: char| c.is_ascii_digit());
+        let y_is_number = y.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = match (x_is_number, y_is_number) {
+            (true, true) => {
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => x.cmp(y),
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+    a.len().cmp(&b.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constraint(spec: &str) -> Option<(String, String)> {
+        parse_spec(spec).unwrap().constraint
+    }
+
+    fn pair(operator: &str, version: &str) -> Option<(String, String)> {
+        Some((operator.to_string(), version.to_string()))
+    }
+
+    #[test]
+    fn numbers_compare_by_value() {
+        assert_eq!(compare_versions("1.10", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.9", "1.10"), Ordering::Less);
+        assert_eq!(compare_versions("2.0", "10.0"), Ordering::Less);
+        assert_eq!(compare_versions("1.2.3", "1.2.3"), Ordering::Equal);
+    }
+
+    #[test]
+    fn leading_zeros_are_ignored() {
+        assert_eq!(compare_versions("1.01", "1.1"), Ordering::Equal);
+        assert_eq!(compare_versions("1.010", "1.9"), Ordering::Greater);
+        assert_eq!(compare_versions("1.00", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn letters_are_older_than_numbers() {
+        assert_eq!(compare_versions("1.0a", "1.0.1"), Ordering::Less);
+        assert_eq!(compare_versions("1.0.1", "1.0a"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0a", "1.0b"), Ordering::Less);
+    }
+
+    #[test]
+    fn more_segments_are_newer() {
+        assert_eq!(compare_versions("1.0.1", "1.0"), Ordering::Greater);
+        assert_eq!(compare_versions("1.0", "1.0.0"), Ordering::Less);
+        assert_eq!(compare_versions("1-0", "1.0"), Ordering::Equal);
+    }
+
+    #[test]
+    fn operators_compare_the_found_version() {
+        assert!(satisfies("2.10", ">=", "2.9"));
+        assert!(satisfies("2.9", ">=", "2.9"));
+        assert!(!satisfies("2.8", ">=", "2.9"));
+        assert!(satisfies("2.9", "<=", "2.9"));
+        assert!(!satisfies("2.10", "<=", "2.9"));
+        assert!(satisfies("2.10", ">", "2.9"));
+        assert!(!satisfies("2.9", ">", "2.9"));
+        assert!(satisfies("2.8", "<", "2.9"));
+        assert!(satisfies("2.09", "=", "2.9"));
+        assert!(!satisfies("2.9.1", "=", "2.9"));
+        assert!(satisfies("2.9.1", "!=", "2.9"));
+        assert!(!satisfies("2.09", "!=", "2.9"));
+    }
+
+    #[test]
+    fn specs_are_a_name_and_an_optional_constraint() {
+        assert_eq!(parse_spec("glfw3").unwrap().name, "glfw3");
+        assert_eq!(constraint("glfw3"), None);
+        assert_eq!(parse_spec(" freetype2 >= 2.10 ").unwrap().name, "freetype2");
+        assert_eq!(constraint(" freetype2 >= 2.10 "), pair(">=", "2.10"));
+        assert_eq!(constraint("zlib>1.2"), pair(">", "1.2"));
+        assert_eq!(constraint("zlib <= 1.3"), pair("<=", "1.3"));
+        assert_eq!(constraint("zlib < 1.3"), pair("<", "1.3"));
+        assert_eq!(constraint("zlib = 1.3"), pair("=", "1.3"));
+        assert_eq!(constraint("zlib != 1.3"), pair("!=", "1.3"));
+    }
+
+    #[test]
+    fn malformed_specs_are_rejected() {
+        for spec in [
+            "",
+            " ",
+            "foo >=",
+            "foo >= ",
+            ">= 1",
+            "foo bar",
+            "foo => 1",
+            "foo == 1",
+            "foo ! 1",
+            "foo >= 1 2",
+            "foo >= 1.0 < 2",
+        ] {
+            assert!(parse_spec(spec).is_none(), "{:?} was accepted", spec);
+        }
+    }
+}
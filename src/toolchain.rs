@@ -0,0 +1,363 @@
+//! This module contains the toolchain binaries are built with, and the platform they are built for.
+//!
+//! Without `--target` binaries are built for the host with the compilers of the config.
+//! `--target <triple>` cross compiles with the toolchain file `./toolchains/<triple>.toml`,
+//! which sets the compilers, ar, sysroot and extra flags of the toolchain and the suffixes
+//! of its binaries. Whatever the file leaves out follows the gnu naming of cross toolchains,
+//! like `aarch64-linux-gnu-gcc` and `aarch64-linux-gnu-ar`, and the operating system in the triple.
+//! Cross compiled binaries and objects are placed in a directory of their triple, so they
+//! never overwrite those built for the host.
+use crate::languages::Language;
+use crate::utils::{self, log, LogLevel};
+use std::path::Path;
+use std::sync::OnceLock;
+use toml::{Table, Value};
+
+static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+
+//the operating systems a triple can name, in the order they are looked for in it
+const KNOWN_OS: [(&str, &str); 7] = [
+    ("mingw", "windows"),
+    ("windows", "windows"),
+    ("cygwin", "windows"),
+    ("android", "android"),
+    ("linux", "linux"),
+    ("darwin", "macos"),
+    ("apple", "macos"),
+];
+
+/// The tools binaries are built with and the platform they run on
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    /// The target triple, like aarch64-linux-gnu, None when building for the host
+    pub triple: Option<String>,
+    /// The toolchain file, if the toolchain was read from one
+    pub path: Option<String>,
+    /// The operating system binaries run on, like linux, windows, android or macos
+    pub os: String,
+    /// Compiles c sources, overriding the compilers of the config
+    pub c_compiler: Option<String>,
+    /// Compiles c++ sources, overriding the compilers of the config
+    pub cxx_compiler: Option<String>,
+    /// Archives static libraries
+    pub ar: String,
+    /// Indexes static libraries
+    pub ranlib: String,
+    /// Passed as --sysroot when compiling and linking
+    pub sysroot: Option<String>,
+    /// The pkg-config system deps are found with, instead of PKG_CONFIG or pkg-config
+    pub pkg_config: Option<String>,
+    /// The directories pkg-config finds the packages of the target in, passed as PKG_CONFIG_LIBDIR
+    pub pkg_config_libdir: Option<String>,
+    /// Put in front of the paths pkg-config returns, passed as PKG_CONFIG_SYSROOT_DIR
+    pub pkg_config_sysroot_dir: Option<String>,
+    /// Flags passed when compiling sources of every language, before those of targets
+    pub cflags: String,
+    /// Flags passed when linking, before those of targets
+    pub ldflags: String,
+    /// Appended to the names of exe targets
+    pub exe_suffix: String,
+    /// Appended to the names of dll targets
+    pub dll_suffix: String,
+}
+
+impl Toolchain {
+    /// Returns the toolchain building for the host, with the compilers of the config
+    pub fn host() -> Toolchain {
+        #[cfg(target_os = "windows")]
+        let os = "windows";
+        #[cfg(target_os = "linux")]
+        let os = "linux";
+        #[cfg(target_os = "android")]
+        let os = "android";
+        let (exe_suffix, dll_suffix) = default_suffixes(os);
+        Toolchain {
+            triple: None,
+            path: None,
+            os: os.to_string(),
+            c_compiler: None,
+            cxx_compiler: None,
+            ar: "ar".to_string(),
+            ranlib: "ranlib".to_string(),
+            sysroot: None,
+            pkg_config: None,
+            pkg_config_libdir: None,
+            pkg_config_sysroot_dir: None,
+            cflags: String::new(),
+            ldflags: String::new(),
+            exe_suffix: exe_suffix.to_string(),
+            dll_suffix: dll_suffix.to_string(),
+        }
+    }
+
+    /// Loads the toolchain of a target triple from ./toolchains/<triple>.toml
+    /// The file is optional, the tools are named after the triple if it leaves them out
+    /// Exits if the triple or the file is invalid
+    /// # Arguments
+    /// * `triple` - The target triple, like aarch64-linux-gnu or x86_64-w64-mingw32
+    pub fn load(triple: &str) -> Toolchain {
+        check_triple(triple);
+        let path = format!("./toolchains/{}.toml", triple);
+        let toolchain_toml = if Path::new(&path).exists() {
+            let contents = std::fs::read_to_string(&path).unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("Could not read toolchain file: {}", path),
+                );
+                log(LogLevel::Error, &format!("  Error: {}", why));
+                std::process::exit(1);
+            });
+            contents.parse::<Table>().unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("Could not parse toolchain file: {}", path),
+                );
+                log(LogLevel::Error, &format!("Error: {}", why));
+                std::process::exit(1);
+            })
+        } else {
+            log(
+                LogLevel::Info,
+                &format!(
+                    "No toolchain file {}, using the {} toolchain on the PATH",
+                    path, triple
+                ),
+            );
+            Table::new()
+        };
+        let get_str = |key: &str| match toolchain_toml.get(key) {
+            Some(Value::String(value)) => Some(value.clone()),
+            Some(_) => {
+                log(
+                    LogLevel::Error,
+                    &format!("{} of toolchain {} is not a string", key, path),
+                );
+                std::process::exit(1);
+            }
+            None => None,
+        };
+        let os = get_str("os")
+            .or_else(|| os_of_triple(triple).map(String::from))
+            .unwrap_or_else(|| {
+                log(
+                    LogLevel::Error,
+                    &format!("Could not tell the operating system of target: {}", triple),
+                );
+                log(
+                    LogLevel::Error,
+                    &format!("Set os in {}, like os = \"linux\"", path),
+                );
+                std::process::exit(1);
+            });
+        let (exe_suffix, dll_suffix) = default_suffixes(&os);
+        let compiler = get_str("compiler");
+        let c_compiler = get_str("c_compiler")
+            .or_else(|| compiler.clone())
+            .unwrap_or_else(|| format!("{}-gcc", triple));
+        let cxx_compiler = get_str("cxx_compiler")
+            .or(compiler)
+            .unwrap_or_else(|| format!("{}-g++", triple));
+        let ar = get_str("ar").unwrap_or_else(|| format!("{}-ar", triple));
+        let ranlib = get_str("ranlib").unwrap_or_else(|| format!("{}-ranlib", triple));
+        let sysroot = get_str("sysroot");
+        //the packages of the target are in the sysroot, never in the directories of the host
+        let pkg_config = get_str("pkg_config");
+        let pkg_config_libdir = get_str("pkg_config_libdir").or_else(|| {
+            let sysroot = sysroot.as_ref()?;
+            let dirs = [
+                format!("{}/usr/lib/pkgconfig", sysroot),
+                format!("{}/usr/lib/{}/pkgconfig", sysroot, triple),
+                format!("{}/usr/share/pkgconfig", sysroot),
+            ];
+            std::env::join_paths(dirs).ok()?.into_string().ok()
+        });
+        let pkg_config_sysroot_dir = get_str("pkg_config_sysroot_dir").or_else(|| sysroot.clone());
+        let cflags = get_str("cflags").unwrap_or_default();
+        let ldflags = get_str("ldflags").unwrap_or_default();
+        let exe_suffix = get_str("exe_suffix").unwrap_or_else(|| exe_suffix.to_string());
+        let dll_suffix = get_str("dll_suffix").unwrap_or_else(|| dll_suffix.to_string());
+        let toolchain = Toolchain {
+            triple: Some(triple.to_string()),
+            path: Path::new(&path).exists().then_some(path),
+            os,
+            c_compiler: Some(c_compiler),
+            cxx_compiler: Some(cxx_compiler),
+            ar,
+            ranlib,
+            sysroot,
+            pkg_config,
+            pkg_config_libdir,
+            pkg_config_sysroot_dir,
+            cflags,
+            ldflags,
+            exe_suffix,
+            dll_suffix,
+        };
+        for (key, compiler) in [
+            ("c_compiler", &toolchain.c_compiler),
+            ("cxx_compiler", &toolchain.cxx_compiler),
+        ] {
+            utils::check_compiler(
+                compiler.as_deref().unwrap_or_default(),
+                &toolchain.origin(key),
+            );
+        }
+        log(
+            LogLevel::Info,
+            &format!(
+                "Building for {} ({}) with {} and {}",
+                triple,
+                toolchain.os,
+                toolchain.c_compiler.as_deref().unwrap_or_default(),
+                toolchain.cxx_compiler.as_deref().unwrap_or_default()
+            ),
+        );
+        toolchain
+    }
+
+    /// Returns true if binaries are built for windows
+    pub fn is_windows(&self) -> bool {
+        self.os == "windows"
+    }
+
+    /// Returns the compiler of the toolchain for sources of a language, if it overrides the config
+    /// Only c and c++ compilers are part of toolchains, so languages like nasm keep their own
+    /// # Arguments
+    /// * `language` - The language of the sources
+    pub fn compiler_of(&self, language: &Language) -> Option<&str> {
+        if language.is_cxx() {
+            self.cxx_compiler.as_deref()
+        } else if language.is_c() {
+            self.c_compiler.as_deref()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the key of the toolchain a compiler is set with, like toolchain.cxx_compiler
+    /// # Arguments
+    /// * `language` - The language of the sources
+    pub fn compiler_key(&self, language: &Language) -> String {
+        let key = if language.is_cxx() {
+            "cxx_compiler"
+        } else {
+            "c_compiler"
+        };
+        self.origin(key)
+    }
+
+    /// Returns where a key of the toolchain is set, to show in errors
+    /// # Arguments
+    /// * `key` - The key, like cflags
+    pub fn origin(&self, key: &str) -> String {
+        let triple = self.triple.as_deref().unwrap_or("host");
+        match &self.path {
+            Some(path) => format!("toolchain.{} of {} in {}", key, triple, path),
+            None => format!("toolchain.{} of {}", key, triple),
+        }
+    }
+
+    /// Returns the flags of the toolchain passed when compiling sources
+    pub fn compile_flags(&self) -> Vec<String> {
+        let mut compile_flags = self.sysroot_flags();
+        compile_flags.extend(utils::split_flags(&self.cflags, &self.origin("cflags")));
+        compile_flags
+    }
+
+    /// Returns the flags of the toolchain passed when linking
+    pub fn link_flags(&self) -> Vec<String> {
+        let mut link_flags = self.sysroot_flags();
+        link_flags.extend(utils::split_flags(&self.ldflags, &self.origin("ldflags")));
+        link_flags
+    }
+
+    //returns the flag telling the compiler where the headers and libraries of the target are
+    fn sysroot_flags(&self) -> Vec<String> {
+        self.sysroot
+            .iter()
+            .map(|sysroot| format!("--sysroot={}", sysroot))
+            .collect()
+    }
+}
+
+/// Selects the toolchain to build with, the host toolchain is used if none is selected
+/// # Arguments
+/// * `toolchain` - The toolchain
+pub fn set_toolchain(toolchain: Toolchain) {
+    if TOOLCHAIN.set(toolchain).is_err() {
+        log(LogLevel::Warn, "Toolchain already selected");
+    }
+}
+
+/// Returns the selected toolchain
+pub fn toolchain() -> &'static Toolchain {
+    TOOLCHAIN.get_or_init(Toolchain::host)
+}
+
+/// Returns the path of the config file of a project
+/// This is builder_cpp.toml, which serves every platform. Projects without it have a legacy
+/// config file per operating system, config_win32.toml for windows and config_linux.toml for
+/// everything else. When cross compiling, the one of the host is used if there is none for the target
+/// # Arguments
+/// * `dir` - The directory of the project
+pub fn config_path(dir: &str) -> String {
+    let dir = dir.trim_end_matches('/');
+    let path = format!("{}/builder_cpp.toml", dir);
+    if Path::new(&path).exists() {
+        return path;
+    }
+    let file_name = |os: &str| {
+        if os == "windows" {
+            "config_win32.toml"
+        } else {
+            "config_linux.toml"
+        }
+    };
+    let legacy_path = format!("{}/{}", dir, file_name(&toolchain().os));
+    let host_path = format!("{}/{}", dir, file_name(&Toolchain::host().os));
+    if Path::new(&legacy_path).exists() {
+        legacy_path
+    } else if Path::new(&host_path).exists() {
+        host_path
+    } else {
+        path
+    }
+}
+
+//returns the suffixes of exe and dll binaries on an operating system
+fn default_suffixes(os: &str) -> (&'static str, &'static str) {
+    match os {
+        "windows" => (".exe", ".dll"),
+        "macos" => ("", ".dylib"),
+        _ => ("", ".so"),
+    }
+}
+
+//returns the operating system a triple names, if it names a known one
+fn os_of_triple(triple: &str) -> Option<&'static str> {
+    let parts: Vec<&str> = triple.split('-').collect();
+    KNOWN_OS
+        .iter()
+        .find(|(name, _)| parts.iter().any(|part| part.starts_with(name)))
+        .map(|(_, os)| *os)
+}
+
+//exits if a triple cannot be used as the name of its directory
+fn check_triple(triple: &str) {
+    let valid = triple.contains('-')
+        && !triple.starts_with('-')
+        && triple
+            .chars()
+            .all(|x| x.is_ascii_alphanumeric() || x == '_' || x == '-' || x == '.');
+    if !valid {
+        log(
+            LogLevel::Error,
+            &format!("Invalid target triple: {}", triple),
+        );
+        log(
+            LogLevel::Error,
+            "Target triples are parts separated by -, like aarch64-linux-gnu or x86_64-w64-mingw32",
+        );
+        std::process::exit(1);
+    }
+}
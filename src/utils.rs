@@ -1,15 +1,25 @@
 //! This file contains various logging and toml parsing functions
 //! used by the builder_cpp library
+use crate::cache::CacheConfig;
+use crate::flags;
+use crate::graph::TargetGraph;
+use crate::hasher::HashAlgorithm;
+use crate::jobs::Jobs;
+use crate::languages::Language;
+use crate::platform;
+use crate::profiles::{self, Profile};
+use crate::system_deps::SystemDep;
+use crate::toolchain;
 use colored::Colorize;
-use std::{fs::File, io::Read, path::Path, process::Command};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use std::{fs, fs::File, io::Read, path::Path, process::Command};
 use toml::{Table, Value};
 
-#[cfg(target_os = "windows")]
-static OBJ_DIR: &str = ".bld_cpp/obj_win32";
-#[cfg(target_os = "linux")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
-#[cfg(target_os = "android")]
-static OBJ_DIR: &str = ".bld_cpp/obj_linux";
+//the arguments in the output of every backtick subcommand that ran in this build
+static SUBCMD_OUTPUTS: LazyLock<Mutex<HashMap<String, Vec<String>>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 //Log utils
 #[derive(PartialEq, PartialOrd, Debug)]
@@ -28,6 +38,8 @@ pub enum LogLevel {
 /// * `message` - The message to log
 /// # Example
 /// ```
+/// use builder_cpp::utils::{log, LogLevel};
+/// let error = "file not found";
 /// log(LogLevel::Info, "Hello World!");
 /// log(LogLevel::Error, &format!("Something went wrong! {}", error));
 /// ```
@@ -40,6 +52,7 @@ pub enum LogLevel {
 /// * `Log`
 /// * `Warn`
 /// * `Error`
+///
 /// If the environment variable is not set, the default log level is `Log`
 pub fn log(level: LogLevel, message: &str) {
     let level_str = match level {
@@ -81,29 +94,239 @@ pub fn log(level: LogLevel, message: &str) {
 #[derive(Debug)]
 pub struct BuildConfig {
     pub compiler: String,
+    pub c_compiler: Option<String>, // compiler of c sources, compiler is used if there is none
+    pub cxx_compiler: Option<String>, // compiler of c++ sources, compiler is used if there is none
     pub packages: Vec<String>,
+    pub jobs: Option<Jobs>,
+    pub hash: HashAlgorithm,
+    pub launcher: Option<String>, // wraps every compile command, like ccache
+    pub cache: Option<CacheConfig>, // the object cache is disabled if there is none
+    pub languages: Vec<Language>,
+    pub profiles: Vec<Profile>,
+}
+
+impl BuildConfig {
+    /// Returns the compiler sources of a language are compiled with
+    /// The compiler of a cross compiling toolchain comes first, then the compiler of the language,
+    /// then c_compiler or cxx_compiler and then compiler
+    /// # Arguments
+    /// * `language` - The language of the sources
+    pub fn compiler_of<'a>(&'a self, language: &'a Language) -> &'a str {
+        if let Some(compiler) = toolchain::toolchain().compiler_of(language) {
+            return compiler;
+        }
+        let family_compiler = if language.is_cxx() {
+            self.cxx_compiler.as_deref()
+        } else if language.is_c() {
+            self.c_compiler.as_deref()
+        } else {
+            None
+        };
+        language
+            .compiler
+            .as_deref()
+            .or(family_compiler)
+            .unwrap_or(&self.compiler)
+    }
+
+    /// Returns the key the compiler of a language is set with, like languages.cxx.compiler
+    /// # Arguments
+    /// * `language` - The language of the sources
+    pub fn compiler_key(&self, language: &Language) -> String {
+        let toolchain = toolchain::toolchain();
+        if toolchain.compiler_of(language).is_some() {
+            toolchain.compiler_key(language)
+        } else if language.compiler.is_some() {
+            format!("languages.{}.compiler", language.name)
+        } else if language.is_cxx() && self.cxx_compiler.is_some() {
+            "build.cxx_compiler".to_string()
+        } else if language.is_c() && self.c_compiler.is_some() {
+            "build.c_compiler".to_string()
+        } else {
+            "build.compiler".to_string()
+        }
+    }
+
+    /// Returns the selected profile
+    /// Exits if the config does not define it
+    pub fn profile(&self) -> &Profile {
+        let name = profiles::profile_name();
+        self.profiles
+            .iter()
+            .find(|x| x.name == name)
+            .unwrap_or_else(|| {
+                log(LogLevel::Error, &format!("Could not find profile: {}", name));
+                std::process::exit(1);
+            })
+    }
+
+    /// Returns the compiler driver targets are linked with
+    /// The c++ driver is needed as soon as one c++ object is linked in, since it adds the c++ runtime
+    /// # Arguments
+    /// * `has_cxx` - If any of the linked objects was compiled from c++
+    pub fn linker(&self, has_cxx: bool) -> &str {
+        let name = if has_cxx { "cxx" } else { "c" };
+        match self.languages.iter().find(|x| x.name == name) {
+            Some(language) => self.compiler_of(language),
+            None => &self.compiler,
+        }
+    }
+
+    /// Returns the key the compiler driver targets are linked with is set with
+    /// # Arguments
+    /// * `has_cxx` - If any of the linked objects was compiled from c++
+    pub fn linker_key(&self, has_cxx: bool) -> String {
+        let name = if has_cxx { "cxx" } else { "c" };
+        match self.languages.iter().find(|x| x.name == name) {
+            Some(language) => self.compiler_key(language),
+            None => "build.compiler".to_string(),
+        }
+    }
 }
 
 /// Struct describing the target config of the local project
 #[derive(Debug, Clone)]
 pub struct TargetConfig {
     pub name: String,
-    pub src: Option<String>, // empty string is used when type is hdr
+    pub config_path: String, // the config file the target is read from
+    pub config_key: String, // the key of the target in its config file, like targets[1]
+    pub src: Vec<String>, // source roots, empty when type is hdr
+    pub sources: Vec<String>, // globs of source files
+    pub exclude: Vec<String>, // patterns of source files to leave out
     pub include_dir: String,
+    pub include_dirs: Vec<String>, // include dirs besides include_dir
     pub typ: String,
-    pub cflags: String,
+    pub cflags: String, // passed when compiling sources of every language
+    pub cxxflags: String, // passed when compiling c++ sources only
+    pub ldflags: Option<String>, // passed when linking, cflags are used if there are none
     pub libs: String,
-    pub deps: Vec<String>,
+    pub link_libs: Vec<String>, // names or paths of libraries, translated for the linker
+    pub system_deps: Vec<SystemDep>, // pkg-config packages the target is built with
+    pub pch: Option<String>, // a header precompiled once and included before every source
+    pub unity: bool, // compile the sources in batches when the whole target is compiled
+    pub unity_batch_size: usize, // the number of sources in every batch
+    pub unity_exclude: Vec<String>, // patterns of sources that are never put in a batch
+    pub warnings: Option<String>, // one of flags::WARNING_LEVELS
+    pub std: Vec<String>, // language standards, each passed to the sources of its language
+    pub optimization: Option<String>, // one of flags::OPTIMIZATION_LEVELS, overrides the profile
+    pub defines: Vec<String>,
+    pub deps: Vec<Dependency>,
+}
+
+/// Struct describing the dependency of a target on another target
+#[derive(Debug, Clone)]
+pub struct Dependency {
+    pub name: String,
+    /// One of private, public or interface.
+    /// Private deps are only used to build the target itself,
+    /// interface deps are only passed on to the targets depending on it
+    /// and public deps are both
+    pub visibility: String,
 }
 
 impl TargetConfig {
-    /// Returns a vec of all filenames ending in .cpp or .c in the src directory
+    /// Describes where a key of the target is set, to point at it in errors
+    /// Keys outside of the target are given by their full name, like profile.release.cflags
+    /// # Arguments
+    /// * `key` - The key, like cflags
+    pub fn origin(&self, key: &str) -> String {
+        if key.contains('.') {
+            format!("{} of target {}", key, self.name)
+        } else {
+            format!(
+                "{}.{} of target {} in {}",
+                self.config_key, key, self.name, self.config_path
+            )
+        }
+    }
+
+    /// Returns the file name of the binary the target produces
+    /// exe and dll targets get the suffixes of the platform built for, like .exe and .so,
+    /// and lib targets are static archives
+    pub fn bin_name(&self) -> String {
+        let toolchain = toolchain::toolchain();
+        let suffix = match self.typ.as_str() {
+            "exe" => toolchain.exe_suffix.as_str(),
+            "dll" => toolchain.dll_suffix.as_str(),
+            "lib" => ".a",
+            _ => "",
+        };
+        format!("{}{}", self.name, suffix)
+    }
+
+    /// Returns the paths of all source files of the target
+    /// These are the files of a known language under the src roots and the files matching the sources globs,
+    /// leaving out those matching an exclude pattern or inside a directory that does
+    /// # Arguments
+    /// * `languages` - The languages sources can be written in
+    pub fn get_src_paths(&self, languages: &[Language]) -> Vec<String> {
+        let mut src_paths = Vec::new();
+        for root in &self.src {
+            let mut root_paths = Vec::new();
+            TargetConfig::walk_src_dir(root, languages, &mut root_paths);
+            root_paths.sort();
+            src_paths.append(&mut root_paths);
+        }
+        for pattern in &self.sources {
+            let paths = glob::glob(pattern).unwrap_or_else(|why| {
+                log(
+                    LogLevel::Error,
+                    &format!("Invalid sources pattern: {} in target: {}", pattern, self.name),
+                );
+                log(LogLevel::Error, &format!("Error: {}", why));
+                std::process::exit(1);
+            });
+            //files of no known language, like headers, are not sources
+            for path in paths.flatten() {
+                let path_str = path.to_str().unwrap().replace('\\', "/");
+                if path.is_file() && Language::of_path(languages, &path_str).is_some() {
+                    src_paths.push(path_str);
+                }
+            }
+        }
+
+        let exclude = self.parse_patterns(&self.exclude, "exclude");
+        let mut seen = Vec::new();
+        src_paths.retain(|path| {
+            let normalized = normalize_path(path);
+            if seen.contains(&normalized) || matches_patterns(&exclude, path) {
+                return false;
+            }
+            seen.push(normalized);
+            true
+        });
+        src_paths
+    }
+
+    /// Returns true if a source is never compiled in a unity batch,
+    /// since it matches a pattern of unity_exclude or is inside a directory that does
     /// # Arguments
-    /// * `path` - The path to the src directory
-    fn get_src_names(path: &str) -> Vec<String> {
-        let mut src_names = Vec::new();
-        let src_path = Path::new(&path);
-        let src_entries = std::fs::read_dir(src_path).unwrap_or_else(|_| {
+    /// * `path` - The path of the source
+    pub fn is_unity_excluded(&self, path: &str) -> bool {
+        let unity_exclude = self.parse_patterns(&self.unity_exclude, "unity_exclude");
+        matches_patterns(&unity_exclude, path)
+    }
+
+    //parses glob patterns of paths, exits if one is invalid
+    fn parse_patterns(&self, patterns: &[String], key: &str) -> Vec<glob::Pattern> {
+        patterns
+            .iter()
+            .map(|pattern| {
+                glob::Pattern::new(normalize_path(pattern).as_str()).unwrap_or_else(|why| {
+                    log(
+                        LogLevel::Error,
+                        &format!("Invalid {} pattern: {} in target: {}", key, pattern, self.name),
+                    );
+                    log(LogLevel::Error, &format!("Error: {}", why));
+                    std::process::exit(1);
+                })
+            })
+            .collect()
+    }
+
+    //adds the paths of the source files in the given directory and its subdirectories
+    fn walk_src_dir(path: &str, languages: &[Language], src_paths: &mut Vec<String>) {
+        let src_entries = std::fs::read_dir(path).unwrap_or_else(|_| {
             log(
                 LogLevel::Error,
                 &format!("Could not read src dir: {}", path),
@@ -111,59 +334,230 @@ impl TargetConfig {
             std::process::exit(1);
         });
         for entry in src_entries {
-            let entry = entry.unwrap();
-            let path = entry.path();
-            if path.is_file() {
-                let file_name = path.file_name().unwrap().to_str().unwrap();
-                if file_name.ends_with(".cpp") || file_name.ends_with(".c") {
-                    src_names.push(file_name.to_string());
-                }
-            } else if path.is_dir() {
-                let dir_name = path.to_str().unwrap().replace("\\", "/");
-                let mut dir_src_names = TargetConfig::get_src_names(&dir_name);
-                src_names.append(&mut dir_src_names);
+            let path = entry.unwrap().path();
+            let path_str = path.to_str().unwrap().replace('\\', "/");
+            if path.is_dir() {
+                TargetConfig::walk_src_dir(&path_str, languages, src_paths);
+            } else if Language::of_path(languages, &path_str).is_some() {
+                src_paths.push(path_str);
             }
         }
-        src_names
     }
+}
 
-    fn arrange_targets(targets: &Vec<TargetConfig>) -> Vec<TargetConfig> {
-        let mut targets = targets.clone();
-        let mut i = 0;
-        while i < targets.len() {
-            let mut j = i + 1;
-            while j < targets.len() {
-                if targets[i].deps.contains(&targets[j].name) {
-                    //Check for circular dependencies
-                    if targets[j].deps.contains(&targets[i].name) {
-                        log(
-                            LogLevel::Error,
-                            &format!(
-                                "Circular dependency found between {} and {}",
-                                targets[i].name, targets[j].name
-                            ),
-                        );
+//returns the path without ./ components, so that paths and patterns can be compared
+fn normalize_path(path: &str) -> String {
+    path.split('/')
+        .filter(|x| *x != ".")
+        .collect::<Vec<&str>>()
+        .join("/")
+}
+
+//returns true if a path, or one of its directories, matches one of the patterns
+fn matches_patterns(patterns: &[glob::Pattern], path: &str) -> bool {
+    let options = glob::MatchOptions {
+        require_literal_separator: true,
+        ..Default::default()
+    };
+    let path = normalize_path(path);
+    Path::new(&path)
+        .ancestors()
+        .any(|x| patterns.iter().any(|pattern| pattern.matches_path_with(x, options)))
+}
+
+//returns a list of strings that may be given as a single string or an array of strings
+//returns an empty list if the key is missing
+fn parse_str_list(target: &Value, key: &str) -> Vec<String> {
+    match target.get(key) {
+        None => Vec::new(),
+        Some(Value::String(value)) => vec![value.clone()],
+        Some(Value::Array(values)) => values
+            .iter()
+            .map(|value| {
+                value
+                    .as_str()
+                    .unwrap_or_else(|| {
+                        log(LogLevel::Error, &format!("{} is a vec of strings", key));
                         std::process::exit(1);
-                    }
-                    let temp = targets[i].clone();
-                    targets[i] = targets[j].clone();
-                    targets[j] = temp;
-                    i = 0;
-                    break;
-                }
-                j += 1;
+                    })
+                    .to_string()
+            })
+            .collect(),
+        Some(_) => {
+            log(
+                LogLevel::Error,
+                &format!("{} is not a string or an array of strings", key),
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Exits if a compiler does not start with a program, since its first flag would run in its place,
+/// or if it takes MSVC style flags, since every command is written with gcc style flags
+/// # Arguments
+/// * `compiler` - The compiler, which may be followed by flags
+/// * `origin` - Where the compiler is set, like build.compiler in ./builder_cpp.toml
+pub fn check_compiler(compiler: &str, origin: &str) {
+    let (reason, hint) = match compiler.split_whitespace().next() {
+        None => (
+            "is empty".to_string(),
+            "It has to start with a compiler, like \"g++\" or \"clang -fcolor-diagnostics\"",
+        ),
+        Some(flag) if flag.starts_with('-') => (
+            format!("starts with the flag {}", flag),
+            "It has to start with a compiler, like \"g++\" or \"clang -fcolor-diagnostics\"",
+        ),
+        Some(program) if flags::is_msvc(program) => (
+            format!("is {}, which takes MSVC style flags", program),
+            "MSVC style compilers are not supported, use gcc, clang or a mingw toolchain",
+        ),
+        Some(_) => return,
+    };
+    log(LogLevel::Error, &format!("{} {}", origin, reason));
+    log(LogLevel::Error, hint);
+    std::process::exit(1);
+}
+
+/// Splits a string of flags into arguments the way a shell would, without running one
+/// Subcommands enclosed in backticks, like `pkg-config --cflags freetype2`, are replaced by the
+/// arguments in their output. Every subcommand runs at most once per build, and its output is
+/// reused by every target and source using it
+/// # Arguments
+/// * `flags` - The flags to split
+/// * `origin` - Where the flags are set, shown if they cannot be split, see TargetConfig::origin
+pub fn split_flags(flags: &str, origin: &str) -> Vec<String> {
+    let parts: Vec<&str> = flags.split('`').collect();
+    if parts.len() % 2 == 0 {
+        log(LogLevel::Error, &format!("Unclosed backtick in: {}", flags));
+        log(LogLevel::Error, &format!("  In {}", origin));
+        std::process::exit(1);
+    }
+    let mut args = Vec::new();
+    // Even entries are plain flags and odd entries are subcmds
+    for (i, part) in parts.into_iter().enumerate() {
+        if i % 2 == 0 {
+            args.extend(split_words(part, flags, origin));
+            continue;
+        }
+        //held while the subcmd runs, so that targets built in parallel do not run it twice
+        let mut subcmd_outputs = SUBCMD_OUTPUTS.lock().unwrap();
+        if let Some(output) = subcmd_outputs.get(part) {
+            args.extend(output.iter().cloned());
+            continue;
+        }
+        let words = split_words(part, flags, origin);
+        let Some((program, subcmd_args)) = words.split_first() else {
+            continue;
+        };
+        let subcmd_failed = |reason: String| -> ! {
+            log(LogLevel::Error, &format!("Failed to execute subcmd: {}", part));
+            log(LogLevel::Error, &format!("  In {}", origin));
+            log(LogLevel::Error, &reason);
+            std::process::exit(1);
+        };
+        let output = Command::new(program)
+            .args(subcmd_args)
+            .output()
+            .unwrap_or_else(|why| subcmd_failed(format!("  Error: {}", why)));
+        if !output.status.success() {
+            subcmd_failed(format!(
+                "  Stderr: {}",
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            ));
+        }
+        let output = split_words(&String::from_utf8_lossy(&output.stdout), part, origin);
+        log(
+            LogLevel::Info,
+            &format!("Subcmd `{}` expanded to: {}", part, join_args(&output)),
+        );
+        args.extend(output.iter().cloned());
+        subcmd_outputs.insert(part.to_string(), output);
+    }
+    args
+}
+
+/// Joins arguments into a single command line, quoting those a shell would split or expand
+/// The result is only shown to users and recorded, commands are always run from their arguments
+/// # Arguments
+/// * `args` - The arguments to join
+pub fn join_args<S: AsRef<str>>(args: &[S]) -> String {
+    let is_plain = |c: char| c.is_ascii_alphanumeric() || "-_+=/.,:@%^".contains(c);
+    args.iter()
+        .map(|arg| {
+            let arg = arg.as_ref();
+            if !arg.is_empty() && arg.chars().all(is_plain) {
+                arg.to_string()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
             }
-            i += 1;
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+//splits words with shell quoting rules, exits if the quotes are unbalanced
+fn split_words(words: &str, flags: &str, origin: &str) -> Vec<String> {
+    shell_words::split(words).unwrap_or_else(|why| {
+        log(LogLevel::Error, &format!("Invalid quoting in: {}", flags));
+        log(LogLevel::Error, &format!("  In {}", origin));
+        log(LogLevel::Error, &format!("  Error: {}", why));
+        std::process::exit(1);
+    })
+}
+
+/// Returns the path of a program, looking for it in PATH unless it is given as a path
+/// # Arguments
+/// * `program` - The name or path of the program
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    if program.contains('/') || program.contains('\\') {
+        return Some(PathBuf::from(program)).filter(|x| x.is_file());
+    }
+    #[cfg(target_os = "windows")]
+    let names = [program.to_string(), format!("{}.exe", program)];
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    let names = [program.to_string()];
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .flat_map(|dir| names.iter().map(move |name| dir.join(name)))
+        .find(|x| x.is_file())
+}
+
+/// Creates a directory and all of its parents, exits if that fails
+/// # Arguments
+/// * `path` - The path of the directory
+pub fn create_dir(path: &str) {
+    if Path::new(path).exists() {
+        return;
+    }
+    fs::create_dir_all(path).unwrap_or_else(|why| {
+        log(LogLevel::Error, &format!("Failed to create {}", path));
+        log(LogLevel::Error, &format!("  Error: {}", why));
+        std::process::exit(1);
+    });
+    log(LogLevel::Info, &format!("Created {}", path));
+}
+
+//copies everything inside a directory into another one
+fn copy_dir_contents(from: &Path, to: &Path) -> std::io::Result<()> {
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        let dest = to.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            fs::create_dir_all(&dest)?;
+            copy_dir_contents(&entry.path(), &dest)?;
+        } else {
+            fs::copy(entry.path(), &dest)?;
         }
-        targets
     }
+    Ok(())
 }
 
 /// This function is used to parse the config file of local project
 /// # Arguments
 /// * `path` - The path to the config file
-/// * `check_dup_src` - If true, the function will check for duplicately named source files
-pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<TargetConfig>) {
+/// * `check_srcs` - If true, the function will check that every target that needs sources has some
+pub fn parse_config(path: &str, check_srcs: bool) -> (BuildConfig, Vec<TargetConfig>) {
     //open toml file and parse it into a string
     let mut file = File::open(path).unwrap_or_else(|_| {
         log(
@@ -188,6 +582,7 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
         log(LogLevel::Error, &format!("Error: {}", e));
         std::process::exit(1);
     });
+    let (config, target_keys) = platform::resolve(config, path);
 
     let mut pkgs: Vec<String> = Vec::new();
     let empty_value = Value::Array(Vec::new());
@@ -199,7 +594,7 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
             std::process::exit(1);
         })
         .get("packages")
-        .unwrap_or_else(|| &empty_value)
+        .unwrap_or(&empty_value)
         .as_array()
         .unwrap_or_else(|| {
             log(LogLevel::Error, "packages is not an array");
@@ -217,16 +612,77 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
         );
     }
 
-    //parse the string into a struct
-    let build_config = BuildConfig {
-        compiler: config["build"]["compiler"]
+    //jobs is optional, either a number or "auto"
+    let jobs = config["build"].get("jobs").map(|jobs| {
+        let parsed = match jobs {
+            Value::Integer(count) => Jobs::parse(&count.to_string()),
+            Value::String(jobs) => Jobs::parse(jobs),
+            _ => None,
+        };
+        parsed.unwrap_or_else(|| {
+            log(
+                LogLevel::Error,
+                "jobs must be a positive number or \"auto\"",
+            );
+            std::process::exit(1);
+        })
+    });
+
+    //hash is optional, sha1 is used by default
+    let hash = match config["build"].get("hash") {
+        Some(hash) => hash
             .as_str()
+            .and_then(HashAlgorithm::parse)
             .unwrap_or_else(|| {
-                log(LogLevel::Error, "Could not find compiler in config file");
+                log(
+                    LogLevel::Error,
+                    "hash must be one of \"sha1\", \"blake3\" or \"xxh3\"",
+                );
                 std::process::exit(1);
-            })
-            .to_string(),
+            }),
+        None => HashAlgorithm::Sha1,
+    };
+
+    //c_compiler and cxx_compiler are optional, compiler may be left out if they are given
+    let get_compiler = |key: &str| {
+        config["build"].get(key).map(|x| {
+            x.as_str()
+                .unwrap_or_else(|| {
+                    log(LogLevel::Error, &format!("{} is not a string", key));
+                    std::process::exit(1);
+                })
+                .to_string()
+        })
+    };
+    let c_compiler = get_compiler("c_compiler");
+    let cxx_compiler = get_compiler("cxx_compiler");
+    //launcher is optional too, and may be empty to run compilers without one
+    let launcher = get_compiler("launcher");
+    for key in ["compiler", "c_compiler", "cxx_compiler"] {
+        if let Some(compiler) = get_compiler(key) {
+            check_compiler(&compiler, &format!("build.{} in {}", key, path));
+        }
+    }
+    let compiler = get_compiler("compiler")
+        .or_else(|| cxx_compiler.clone())
+        .or_else(|| c_compiler.clone())
+        .unwrap_or_else(|| {
+            log(LogLevel::Error, "Could not find compiler in config file");
+            std::process::exit(1);
+        });
+
+    //parse the string into a struct
+    let build_config = BuildConfig {
+        compiler,
+        c_compiler,
+        cxx_compiler,
         packages: pkgs,
+        jobs,
+        hash,
+        launcher,
+        cache: CacheConfig::parse(&config),
+        languages: Language::parse_languages(&config),
+        profiles: Profile::parse_profiles(&config),
     };
 
     let mut tgt = Vec::new();
@@ -235,55 +691,179 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
         std::process::exit(1);
     });
 
-    for target in targets {
-        let mut deps: Vec<String> = Vec::new();
+    for (target, config_key) in targets.iter().zip(target_keys) {
+        let mut deps: Vec<Dependency> = Vec::new();
         let empty_value = Value::Array(Vec::new());
         //deps is optional
         let deps_toml = target
             .get("deps")
-            .unwrap_or_else(|| &empty_value)
+            .unwrap_or(&empty_value)
             .as_array()
             .unwrap_or_else(|| {
                 log(LogLevel::Error, "Deps is not an array");
                 std::process::exit(1);
             });
         for dep in deps_toml {
-            deps.push(
-                dep.as_str()
-                    .unwrap_or_else(|| {
-                        log(LogLevel::Error, "Deps are a vec of strings");
+            //a dep is either the name of a target or a table with its name and visibility
+            let (name, visibility) = match dep {
+                Value::String(name) => (name.as_str(), "private"),
+                Value::Table(dep) => (
+                    dep.get("name").and_then(|x| x.as_str()).unwrap_or_else(|| {
+                        log(LogLevel::Error, "Could not find name of dep in config file");
                         std::process::exit(1);
-                    })
-                    .to_string(),
-            );
+                    }),
+                    dep.get("visibility")
+                        .map(|x| {
+                            x.as_str().unwrap_or_else(|| {
+                                log(LogLevel::Error, "Visibility of dep is not a string");
+                                std::process::exit(1);
+                            })
+                        })
+                        .unwrap_or("private"),
+                ),
+                _ => {
+                    log(
+                        LogLevel::Error,
+                        "Deps are a vec of strings or { name, visibility } tables",
+                    );
+                    std::process::exit(1);
+                }
+            };
+            //the sample config of a new project contains an empty dep
+            if name.is_empty() {
+                continue;
+            }
+            if visibility != "private" && visibility != "public" && visibility != "interface" {
+                log(
+                    LogLevel::Error,
+                    &format!("Visibility of dep {} must be private, public or interface", name),
+                );
+                std::process::exit(1);
+            }
+            deps.push(Dependency {
+                name: name.to_string(),
+                visibility: visibility.to_string(),
+            });
         }
-        
-        let target_type = &target["type"];
-        let target_config = TargetConfig {
-            name: target["name"]
+
+        let mut defines: Vec<String> = Vec::new();
+        //defines is optional, and either a vec of NAME or NAME=VALUE strings or a table of names to values
+        match target.get("defines").unwrap_or(&empty_value) {
+            Value::Array(defines_toml) => {
+                for define in defines_toml {
+                    defines.push(
+                        define
+                            .as_str()
+                            .unwrap_or_else(|| {
+                                log(LogLevel::Error, "Defines are a vec of strings");
+                                std::process::exit(1);
+                            })
+                            .to_string(),
+                    );
+                }
+            }
+            Value::Table(defines_toml) => {
+                for (name, value) in defines_toml {
+                    match value {
+                        Value::String(value) => defines.push(format!("{}={}", name, value)),
+                        Value::Integer(value) => defines.push(format!("{}={}", name, value)),
+                        Value::Float(value) => defines.push(format!("{}={}", name, value)),
+                        //true defines the name without a value and false leaves it undefined
+                        Value::Boolean(true) => defines.push(name.clone()),
+                        Value::Boolean(false) => {}
+                        _ => {
+                            log(
+                                LogLevel::Error,
+                                &format!("Define {} is not a string, number or bool", name),
+                            );
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+            _ => {
+                log(LogLevel::Error, "Defines is not an array or a table");
+                std::process::exit(1);
+            }
+        }
+
+        //include_dir may be left out if include_dirs is given, the first of which takes its place
+        let mut include_dirs = parse_str_list(target, "include_dirs");
+        let include_dir = match target.get("include_dir") {
+            Some(include_dir) => include_dir
                 .as_str()
                 .unwrap_or_else(|| {
-                    log(LogLevel::Error, "Could not find name in config file");
+                    log(LogLevel::Error, "include_dir is not a string");
                     std::process::exit(1);
                 })
                 .to_string(),
-            src: match target_type.as_str() {
-                Some("dll" | "exe") => Some(target["src"]
-                .as_str()
+            None if !include_dirs.is_empty() => include_dirs.remove(0),
+            None => {
+                log(
+                    LogLevel::Error,
+                    "Could not find include_dir or include_dirs in config file",
+                );
+                std::process::exit(1);
+            }
+        };
+
+        let warnings = target.get("warnings").map(|x| {
+            x.as_str()
+                .filter(|x| flags::WARNING_LEVELS.contains(x))
                 .unwrap_or_else(|| {
-                    log(LogLevel::Info, "Could not find src in config file");
+                    log(
+                        LogLevel::Error,
+                        &format!("warnings must be one of {}", flags::WARNING_LEVELS.join(", ")),
+                    );
                     std::process::exit(1);
-                }).to_string()), 
-                Some("hdr") => None,
-                _ => None, 
-            },
-            include_dir: target["include_dir"]
+                })
+                .to_string()
+        });
+        let std = parse_str_list(target, "std");
+        for std in &std {
+            if !std.starts_with('c') && !std.starts_with("gnu") {
+                log(
+                    LogLevel::Error,
+                    &format!("Invalid std: {}, it must be like c11, gnu17 or c++20", std),
+                );
+                std::process::exit(1);
+            }
+        }
+        let optimization = target.get("optimization").map(flags::parse_optimization);
+
+        //cflags and libs are optional, for flags the other keys have no equivalent of
+        let get_flags = |key: &str| {
+            target
+                .get(key)
+                .map(|x| {
+                    x.as_str().unwrap_or_else(|| {
+                        log(LogLevel::Error, &format!("{} is not a string", key));
+                        std::process::exit(1);
+                    })
+                })
+                .unwrap_or_default()
+                .to_string()
+        };
+
+        let target_type = &target["type"];
+        let target_config = TargetConfig {
+            name: target["name"]
                 .as_str()
                 .unwrap_or_else(|| {
-                    log(LogLevel::Error, "Could not find include_dir in config file");
+                    log(LogLevel::Error, "Could not find name in config file");
                     std::process::exit(1);
                 })
                 .to_string(),
+            config_path: path.to_string(),
+            config_key: config_key.clone(),
+            src: match target_type.as_str() {
+                Some("dll" | "exe" | "lib") => parse_str_list(target, "src"),
+                _ => Vec::new(),
+            },
+            sources: parse_str_list(target, "sources"),
+            exclude: parse_str_list(target, "exclude"),
+            include_dir,
+            include_dirs,
             typ: target["type"]
                 .as_str()
                 .unwrap_or_else(|| {
@@ -291,33 +871,85 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
                     std::process::exit(1);
                 })
                 .to_string(),
-            cflags: target["cflags"]
-                .as_str()
-                .unwrap_or_else(|| {
-                    log(LogLevel::Error, "Could not find cflags in config file");
-                    std::process::exit(1);
+            cflags: get_flags("cflags"),
+            cxxflags: get_flags("cxxflags"),
+            ldflags: target.get("ldflags").map(|x| {
+                x.as_str()
+                    .unwrap_or_else(|| {
+                        log(LogLevel::Error, "ldflags is not a string");
+                        std::process::exit(1);
+                    })
+                    .to_string()
+            }),
+            libs: get_flags("libs"),
+            unity: target
+                .get("unity")
+                .map(|x| {
+                    x.as_bool().unwrap_or_else(|| {
+                        log(LogLevel::Error, "unity is not a boolean");
+                        std::process::exit(1);
+                    })
                 })
-                .to_string(),
-            libs: target["libs"]
-                .as_str()
-                .unwrap_or_else(|| {
-                    log(LogLevel::Error, "Could not find libs in config file");
-                    std::process::exit(1);
+                .unwrap_or(false),
+            unity_batch_size: target
+                .get("unity_batch_size")
+                .map(|x| {
+                    x.as_integer().filter(|x| *x > 0).unwrap_or_else(|| {
+                        log(
+                            LogLevel::Error,
+                            "unity_batch_size must be a positive integer",
+                        );
+                        std::process::exit(1);
+                    }) as usize
                 })
-                .to_string(),
+                .unwrap_or(8),
+            unity_exclude: parse_str_list(target, "unity_exclude"),
+            pch: target.get("pch").map(|x| {
+                x.as_str()
+                    .unwrap_or_else(|| {
+                        log(LogLevel::Error, "pch is not a string");
+                        std::process::exit(1);
+                    })
+                    .to_string()
+            }),
+            link_libs: parse_str_list(target, "link_libs"),
+            system_deps: parse_str_list(target, "system_deps")
+                .iter()
+                .map(|x| {
+                    let origin = format!("{}.system_deps in {}", config_key, path);
+                    SystemDep::parse(x, &origin)
+                })
+                .collect(),
+            warnings,
+            std,
+            optimization,
+            defines,
             deps,
         };
-        if target_config.typ != "exe" && target_config.typ != "dll" {
-			if target_config.typ == "hdr" { 
-			log(LogLevel::Warn, "header pkg recognied, implementation might be incomplete") 
-			}
-            // log(LogLevel::Error, "Type must be exe or dll");
-            // std::process::exit(1);
+        match target_config.typ.as_str() {
+            "exe" | "dll" | "lib" => {
+                if target_config.src.is_empty() && target_config.sources.is_empty() {
+                    log(LogLevel::Error, "Could not find src or sources in config file");
+                    log(LogLevel::Error, &format!("Target: {}", target_config.name));
+                    std::process::exit(1);
+                }
+            }
+            "hdr" => {
+                log(LogLevel::Warn, "header pkg recognied, implementation might be incomplete")
+            }
+            _ => {
+                log(LogLevel::Error, "Type must be exe, dll, lib or hdr");
+                log(
+                    LogLevel::Error,
+                    &format!("Target: {} is a {}", target_config.name, target_config.typ),
+                );
+                std::process::exit(1);
+            }
         }
         tgt.push(target_config);
     }
 
-    if tgt.len() == 0 {
+    if tgt.is_empty() {
         log(LogLevel::Error, "No targets found");
         std::process::exit(1);
     }
@@ -334,52 +966,20 @@ pub fn parse_config(path: &str, check_dup_src: bool) -> (BuildConfig, Vec<Target
         }
     }
 
-    if check_dup_src {
+    if check_srcs {
         for target in &tgt {
-            match target.typ.as_str() {
-                "dll" | "exe" => {
-                    let mut src_file_names = TargetConfig::get_src_names(
-                        target.src.as_ref()
-                            .unwrap_or_else(|| {log(
-                            LogLevel::Error,
-                            &format!("No source files found for target: {}", 
-                                target.name),
-                        );
-                        std::process::exit(1);}).as_str());
-                    src_file_names.sort();
-                    if src_file_names.len() == 0 {
-                        log(
-                            LogLevel::Error,
-                            &format!("No source files found for target: {}", 
-                                target.name),
-                        );
-                        std::process::exit(1);
-                    } 
-                    for i in 0..src_file_names.len() - 1 {
-                        if src_file_names[i] == src_file_names[i + 1] {
-                            log(LogLevel::Error,
-                                &format!("Duplicate source files found for target: {}",
-                                target.name),
-                            );
-                            log(LogLevel::Error, 
-                                &format!("Source files must be unique"));
-                            log(LogLevel::Error,
-                                &format!("Duplicate file: {}", src_file_names[i]),
-                            );
-                            std::process::exit(1);
-                        }
-                    }
-                },
-                "hdr" => {},
-                _ => {},
+            //the same sources are collected here as in the builder
+            if target.typ != "hdr" && target.get_src_paths(&build_config.languages).is_empty() {
+                log(
+                    LogLevel::Error,
+                    &format!("No source files found for target: {}", target.name),
+                );
+                std::process::exit(1);
             }
-                
         }
     }
 
-    let tgt_arranged = TargetConfig::arrange_targets(&tgt);
-
-    (build_config, tgt_arranged)
+    (build_config, tgt)
 }
 
 #[derive(Debug)]
@@ -412,17 +1012,10 @@ impl Package {
 
     /// Updates the package to latest commit
     pub fn update(&self) {
-        let mut cmd = String::from("cd");
-        cmd.push_str(&format!(" ./.bld_cpp/sources/{}", self.name));
         log(LogLevel::Log, &format!("Updating package: {}", self.name));
-        cmd.push_str(" &&");
-        cmd.push_str(" git");
-        cmd.push_str(" pull");
-        cmd.push_str(" origin");
-        cmd.push_str(&format!(" {}", self.branch));
-        let com = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
+        let com = Command::new("git")
+            .args(["pull", "origin", &self.branch])
+            .current_dir(format!("./.bld_cpp/sources/{}", self.name))
             .output()
             .unwrap_or_else(|e| {
                 log(LogLevel::Error, &format!("Failed to update package: {}", e));
@@ -453,17 +1046,10 @@ impl Package {
 
     /// Restores package to last offline commit
     pub fn restore(&self) {
-        let mut cmd = String::from("cd");
-        cmd.push_str(&format!(" ./.bld_cpp/sources/{}", self.name));
         log(LogLevel::Log, &format!("Updating package: {}", self.name));
-        cmd.push_str(" &&");
-        cmd.push_str(" git");
-        cmd.push_str(" reset");
-        cmd.push_str(" --hard");
-        cmd.push_str(&format!(" {}", self.branch));
-        let com = Command::new("sh")
-            .arg("-c")
-            .arg(cmd)
+        let com = Command::new("git")
+            .args(["reset", "--hard", &self.branch])
+            .current_dir(format!("./.bld_cpp/sources/{}", self.name))
             .output()
             .unwrap_or_else(|e| {
                 log(
@@ -507,7 +1093,15 @@ impl Package {
         let mut branch = String::new();
         let mut build_config = BuildConfig {
             compiler: String::new(),
+            c_compiler: None,
+            cxx_compiler: None,
             packages: Vec::new(),
+            jobs: None,
+            hash: HashAlgorithm::Sha1,
+            launcher: None,
+            cache: None,
+            languages: Vec::new(),
+            profiles: Vec::new(),
         };
         let mut target_configs = Vec::new();
 
@@ -528,17 +1122,7 @@ impl Package {
             name = repo.split("/").collect::<Vec<&str>>()[1].to_string();
             let source_dir = format!("./.bld_cpp/sources/{}/", name);
             if !Path::new(&source_dir).exists() {
-                Command::new("mkdir")
-                    .arg("-p")
-                    .arg(&source_dir)
-                    .output()
-                    .expect("Failed to execute mkdir");
-                if !Path::new(&source_dir).exists() {
-                    log(LogLevel::Error, &format!("Failed to create {}", source_dir));
-                    std::process::exit(1);
-                } else {
-                    log(LogLevel::Info, &format!("Created {}", source_dir));
-                }
+                create_dir(&source_dir);
                 log(
                     LogLevel::Log,
                     &format!("Cloning {} into {}", repo, source_dir),
@@ -563,44 +1147,47 @@ impl Package {
                     std::process::exit(1);
                 }
             }
-            #[cfg(target_os = "linux")]
-            let pkg_toml = format!("{}/config_linux.toml", source_dir).replace("//", "/");
-            #[cfg(target_os = "android")]
-            let pkg_toml = format!("{}/config_linux.toml", source_dir).replace("//", "/");
-            #[cfg(target_os = "windows")]
-            let pkg_toml = format!("{}/config_win32.toml", source_dir).replace("//", "/");
+            let pkg_toml = toolchain::config_path(&source_dir).replace("//", "/");
 
             let (pkg_bld_config_toml, pkg_targets_toml) = parse_config(&pkg_toml, false);
             log(LogLevel::Info, &format!("Parsed {}", pkg_toml));
 
-            if pkg_bld_config_toml.packages.len() > 0 {
+            let mut foreign_names = Vec::new();
+            if !pkg_bld_config_toml.packages.is_empty() {
                 for foreign_package in Package::parse_packages(&pkg_toml) {
+                    foreign_names.extend(foreign_package.target_configs.iter().map(|x| x.name.clone()));
                     packages.push(foreign_package);
                 }
             }
+            let pkg_targets_toml =
+                TargetGraph::new(&pkg_targets_toml, &foreign_names).arrange(&pkg_targets_toml);
 
             build_config = pkg_bld_config_toml;
             build_config.compiler = build_config_toml.compiler.clone();
-            if !Path::new(OBJ_DIR).exists() {
-                let cmd = Command::new("mkdir").arg("-p").arg(OBJ_DIR).output();
-                if cmd.is_err() {
-                    log(LogLevel::Error, &format!("Failed to create {}", OBJ_DIR));
-                    std::process::exit(1);
-                }
-                log(LogLevel::Info, &format!("Created {}", OBJ_DIR));
-            }
+            build_config.c_compiler = build_config_toml.c_compiler.clone();
+            build_config.cxx_compiler = build_config_toml.cxx_compiler.clone();
+            //packages are built with the profiles of the project using them
+            build_config.profiles = build_config_toml.profiles.clone();
+            create_dir(&profiles::obj_dir());
 
             let tgt_configs = pkg_targets_toml;
             for mut tgt in tgt_configs {
-                if tgt.typ != "dll" {
+                if tgt.typ != "dll" && tgt.typ != "lib" {
                     continue;
                 }
-                tgt.src = tgt.src.map(|src| {
-                    format!("{}/{}", source_dir, src)
+                //source paths and patterns are relative to the package
+                let in_package = |path: &String| {
+                    format!("{}/{}", source_dir, path)
                         .replace("\\", "/")
                         .replace("/./", "/")
                         .replace("//", "/")
-                    });
+                };
+                tgt.src = tgt.src.iter().map(in_package).collect();
+                tgt.sources = tgt.sources.iter().map(in_package).collect();
+                tgt.exclude = tgt.exclude.iter().map(in_package).collect();
+                tgt.include_dirs = tgt.include_dirs.iter().map(in_package).collect();
+                tgt.pch = tgt.pch.as_ref().map(in_package);
+                tgt.unity_exclude = tgt.unity_exclude.iter().map(in_package).collect();
 
                 let old_inc_dir = tgt.include_dir.clone();
                 tgt.include_dir = format!("./.bld_cpp/includes/{}", name)
@@ -608,36 +1195,20 @@ impl Package {
                     .replace("/./", "/")
                     .replace("//", "/");
                 if !Path::new(&tgt.include_dir).exists() {
-                    let cmd = Command::new("mkdir")
-                        .arg("-p")
-                        .arg(&tgt.include_dir)
-                        .output();
-                    if cmd.is_err() {
-                        log(
-                            LogLevel::Error,
-                            &format!("Failed to create {}", tgt.include_dir),
-                        );
-                        std::process::exit(1);
-                    }
-                    log(LogLevel::Info, &format!("Created {}", tgt.include_dir));
-                    let mut cm = String::new();
-                    cm.push_str("cp -r ");
-                    cm.push_str(
-                        &format!("{}/{}/* ", source_dir, old_inc_dir)
-                            .replace("\\", "/")
-                            .replace("/./", "/")
-                            .replace("//", "/"),
-                    );
-                    cm.push_str(&tgt.include_dir);
-                    cm.push_str("/ ");
-                    let cmd = Command::new("sh").arg("-c").arg(&cm).output();
-                    if cmd.is_err() {
-                        log(
-                            LogLevel::Error,
-                            &format!("Failed to create {}", tgt.include_dir),
-                        );
-                        std::process::exit(1);
-                    }
+                    create_dir(&tgt.include_dir);
+                    let old_inc_dir = format!("{}/{}", source_dir, old_inc_dir)
+                        .replace("\\", "/")
+                        .replace("/./", "/")
+                        .replace("//", "/");
+                    copy_dir_contents(Path::new(&old_inc_dir), Path::new(&tgt.include_dir))
+                        .unwrap_or_else(|why| {
+                            log(
+                                LogLevel::Error,
+                                &format!("Failed to copy {} to {}", old_inc_dir, tgt.include_dir),
+                            );
+                            log(LogLevel::Error, &format!("  Error: {}", why));
+                            std::process::exit(1);
+                        });
                 }
                 target_configs.push(tgt);
             }